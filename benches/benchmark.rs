@@ -15,6 +15,8 @@ fn main() {
     bench_coarsetime_recent(options);
     bench_coarsetime_elapsed(options);
     bench_coarsetime_elapsed_since_recent(options);
+    #[cfg(feature = "tsc")]
+    bench_tsc_now(options);
     bench_stdlib_now(options);
     bench_stdlib_elapsed(options);
 }
@@ -47,6 +49,14 @@ fn bench_coarsetime_elapsed_since_recent(options: &Options) {
     println!("coarsetime_since_recent(): {}", res.throughput(1));
 }
 
+#[cfg(feature = "tsc")]
+fn bench_tsc_now(options: &Options) {
+    let b = Bench::new();
+    TscInstant::now();
+    let res = b.run(options, TscInstant::now);
+    println!("tsc_now():                 {}", res.throughput(1));
+}
+
 fn bench_stdlib_now(options: &Options) {
     let b = Bench::new();
     let res = b.run(options, time::Instant::now);
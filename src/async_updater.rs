@@ -0,0 +1,175 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use super::coarse_clock::*;
+use super::updater::{update, Caches};
+
+/// A service to periodically call `Instant::update()`, `BootInstant::update()`
+/// and `Clock::update()` from a task running on an async runtime, instead of
+/// a dedicated thread.
+///
+/// The `tokio` feature enables spawning the updater on a Tokio runtime, and
+/// the `smol` feature on the global `smol` executor.
+#[derive(Debug)]
+pub struct AsyncUpdater {
+    period: time::Duration,
+    caches: Caches,
+    clock: &'static CoarseClock,
+    running: Arc<AtomicBool>,
+    task: Option<Task>,
+}
+
+#[derive(Debug)]
+enum Task {
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::task::JoinHandle<()>),
+    #[cfg(feature = "smol")]
+    Smol(smol::Task<()>),
+}
+
+impl AsyncUpdater {
+    /// Creates a new `AsyncUpdater` with the specified update period, in
+    /// milliseconds.
+    pub fn new(period_millis: u64) -> AsyncUpdater {
+        AsyncUpdater {
+            period: time::Duration::from_millis(period_millis),
+            caches: Caches::default(),
+            clock: CoarseClock::global(),
+            running: Arc::new(AtomicBool::new(false)),
+            task: None,
+        }
+    }
+
+    /// Selects the cached values to refresh
+    pub fn caches(mut self, caches: Caches) -> AsyncUpdater {
+        self.caches = caches;
+        self
+    }
+
+    /// Selects the `CoarseClock` instance to refresh, instead of the
+    /// process-wide default one
+    pub fn clock(mut self, clock: &'static CoarseClock) -> AsyncUpdater {
+        self.clock = clock;
+        self
+    }
+
+    /// Spawns a task to call `Instant::update()` periodically.
+    ///
+    /// The task is spawned on the current Tokio runtime if there is one,
+    /// and on the global `smol` executor otherwise. Tokio runtimes must have
+    /// the time driver enabled.
+    ///
+    /// Returns an error if the update period is zero.
+    pub fn start(self) -> Result<Self, io::Error> {
+        #[cfg(feature = "tokio")]
+        {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                return self.start_tokio(&handle);
+            }
+        }
+        #[cfg(feature = "smol")]
+        {
+            self.start_smol()
+        }
+        #[cfg(not(feature = "smol"))]
+        {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "no Tokio runtime is running",
+            ))
+        }
+    }
+
+    /// Spawns a task to call `Instant::update()` periodically on the given
+    /// Tokio runtime
+    ///
+    /// Returns an error if the update period is zero.
+    #[cfg(feature = "tokio")]
+    pub fn start_tokio(mut self, handle: &tokio::runtime::Handle) -> Result<Self, io::Error> {
+        self.check_period()?;
+        let caches = self.caches;
+        let clock = self.clock;
+        let running = self.running.clone();
+        running.store(true, Ordering::Relaxed);
+        let _guard = handle.enter();
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + self.period, self.period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let task = handle.spawn(async move {
+            while running.load(Ordering::Relaxed) {
+                interval.tick().await;
+                update(caches, clock);
+            }
+        });
+        self.task = Some(Task::Tokio(task));
+        update(caches, clock);
+        Ok(self)
+    }
+
+    /// Spawns a task to call `Instant::update()` periodically on the global
+    /// `smol` executor
+    ///
+    /// Returns an error if the update period is zero.
+    #[cfg(feature = "smol")]
+    pub fn start_smol(mut self) -> Result<Self, io::Error> {
+        use smol::stream::StreamExt;
+
+        self.check_period()?;
+
+        let caches = self.caches;
+        let clock = self.clock;
+        let running = self.running.clone();
+        running.store(true, Ordering::Relaxed);
+        let mut timer = smol::Timer::interval(self.period);
+        let task = smol::spawn(async move {
+            while running.load(Ordering::Relaxed) {
+                timer.next().await;
+                update(caches, clock);
+            }
+        });
+        self.task = Some(Task::Smol(task));
+        update(caches, clock);
+        Ok(self)
+    }
+
+    /// Stops the periodic updates
+    pub fn stop(mut self) -> Result<(), io::Error> {
+        self.running.store(false, Ordering::Relaxed);
+        match self.task.take() {
+            #[cfg(feature = "tokio")]
+            Some(Task::Tokio(task)) => task.abort(),
+            #[cfg(feature = "smol")]
+            Some(Task::Smol(task)) => drop(task),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "updater is not running",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn check_period(&self) -> Result<(), io::Error> {
+        if self.period.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the update period must not be zero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AsyncUpdater {
+    fn drop(&mut self) {
+        // Like a thread, a Tokio task keeps running after its handle is
+        // dropped, but a `smol` task would be cancelled
+        #[cfg(feature = "smol")]
+        if let Some(Task::Smol(task)) = self.task.take() {
+            task.detach();
+        }
+    }
+}
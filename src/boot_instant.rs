@@ -0,0 +1,73 @@
+#[allow(unused_imports)]
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::helpers::*;
+#[allow(unused_imports)]
+use super::instant::*;
+
+/// A measurement of a *monotonically* increasing clock that keeps running
+/// while the system is suspended.
+/// Opaque and useful only with `Duration`.
+///
+/// Unlike `Instant`, durations computed with `BootInstant` include the time
+/// the system spent in suspend, which makes it suitable for leases and
+/// expirations.
+///
+/// On platforms without a suspend-aware clock, this behaves like `Instant`.
+///
+/// It is displayed like an `Instant`, as a duration since an unspecified origin.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq)]
+pub struct BootInstant(u64);
+
+static RECENT: AtomicU64 = AtomicU64::new(0);
+
+impl_cached_instant!(BootInstant, "a");
+
+impl BootInstant {
+    /// Returns an instant corresponding to the latest update
+    pub fn recent() -> BootInstant {
+        match Self::_recent() {
+            0 => BootInstant::now(),
+            recent => BootInstant(recent),
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn _now() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_gettime(libc::CLOCK_BOOTTIME, tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
+    }
+
+    #[cfg(target_os = "macos")]
+    pub(crate) fn _now() -> u64 {
+        let nsec = unsafe { clock_gettime_nsec_np(CLOCK_MONOTONIC) };
+        _nsecs_to_u64(nsec)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+    pub(crate) fn _now() -> u64 {
+        Instant::_os_now()
+    }
+
+    #[inline]
+    fn _update(now: u64) {
+        RECENT.fetch_max(now, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn _recent() -> u64 {
+        let recent = RECENT.load(Ordering::Relaxed);
+        if recent != 0 {
+            recent
+        } else {
+            let now = Self::_now();
+            Self::_update(now);
+            Self::_recent()
+        }
+    }
+}
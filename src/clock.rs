@@ -4,11 +4,23 @@
 )))]
 use std::time;
 
-use std::sync::atomic::{AtomicU64, Ordering};
-
-use super::Duration;
+#[cfg(not(all(
+    any(target_arch = "wasm32", target_arch = "wasm64"),
+    target_os = "unknown"
+)))]
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+#[allow(unused_imports)]
+use std::mem::MaybeUninit;
+use std::ops::*;
 
-static RECENT: AtomicU64 = AtomicU64::new(0);
+use super::coarse_clock::GLOBAL;
+#[allow(unused_imports)]
+use super::helpers::*;
+use super::refresh;
+use super::signed_duration::*;
+use super::{BootInstant, Duration};
 
 #[cfg(all(
     any(target_arch = "wasm32", target_arch = "wasm64"),
@@ -33,44 +45,366 @@ pub struct Clock;
 /// Alias for `Duration`.
 pub type UnixTimeStamp = Duration;
 
+/// A measurement of the system clock (*wall clock*), as elapsed time since
+/// the UNIX epoch.
+///
+/// Unlike instants, dates can be affected by clock adjustments. Subtracting
+/// two dates returns a `Duration`, and adding or subtracting a `Duration` to a
+/// date returns another `Date`.
+///
+/// Dates are displayed as RFC 3339 timestamps in UTC. A precision of up to 3
+/// shows milliseconds, and a larger one shows microseconds.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq)]
+pub struct Date(u64);
+
+/// An error returned when a date cannot be parsed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ParseDateError(pub(crate) ());
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid or unsupported date")
+    }
+}
+
+impl Error for ParseDateError {}
+
 impl Clock {
+    /// Returns the current date
+    #[inline]
+    pub fn now() -> Date {
+        Date(unix_ts())
+    }
+
+    /// Returns the date of the latest explicit time update
+    #[inline]
+    pub fn recent() -> Date {
+        Date::from_unix_timestamp(Self::recent_since_epoch())
+    }
+
+    /// Returns the current date, using the most precise clock available
+    ///
+    /// `now()` is generally preferred over this function.
+    #[inline]
+    pub fn now_precise() -> Date {
+        Date(unix_ts_precise())
+    }
+
     /// Returns the elapsed time since the UNIX epoch
+    ///
+    /// On Linux and Android, this uses `CLOCK_REALTIME_COARSE`, and on FreeBSD
+    /// `CLOCK_REALTIME_FAST`. These are faster, but less precise than the
+    /// regular system clock.
     #[inline]
     pub fn now_since_epoch() -> UnixTimeStamp {
         Duration::from_u64(unix_ts())
     }
 
+    /// Returns the elapsed time since the UNIX epoch, using the most precise
+    /// clock available
+    ///
+    /// `now_since_epoch()` is generally preferred over this function.
+    #[inline]
+    pub fn now_since_epoch_precise() -> UnixTimeStamp {
+        Duration::from_u64(unix_ts_precise())
+    }
+
     /// Returns the elapsed time since the UNIX epoch, based on the latest
     /// explicit time update
+    ///
+    /// If self-refresh is enabled with `set_refresh_every()`, the cached
+    /// system time is also periodically updated by this function.
     #[inline]
     pub fn recent_since_epoch() -> UnixTimeStamp {
-        Duration::from_u64(RECENT.load(Ordering::Relaxed))
+        // The cached time is returned even after refreshing it, as another
+        // thread may have stored a more recent one
+        if refresh::CLOCK.is_due() {
+            GLOBAL.update_since_epoch();
+        }
+        GLOBAL.recent_since_epoch()
+    }
+
+    /// Makes `recent()` and `recent_since_epoch()` update the cached system
+    /// time once every `calls` calls on each thread, so that it stays
+    /// approximately fresh without an `Updater`.
+    ///
+    /// This is a process-wide setting. `0` disables self-refresh, which is
+    /// the default.
+    pub fn set_refresh_every(calls: u32) {
+        refresh::CLOCK.set(calls);
+    }
+
+    /// Returns the number of calls to `recent_since_epoch()` after which the
+    /// cached system time is updated, or `0` if self-refresh is disabled
+    pub fn refresh_every() -> u32 {
+        refresh::CLOCK.get()
     }
 
     /// Updates the cached system time.
     ///
     /// This function should be called frequently, for example in an event loop
     /// or using an `Updater` task.
+    ///
+    /// The cached system time never moves backwards, so after the system time
+    /// is set backwards, it doesn't change until the system time catches up.
     #[inline]
     pub fn update() {
-        let now = unix_ts();
-        RECENT.store(now, Ordering::Relaxed)
+        GLOBAL.update_since_epoch();
+    }
+
+    /// Returns the granularity of the system clock used by `now()` and
+    /// `update()`
+    pub fn resolution() -> Duration {
+        Duration::from_u64(unix_ts_resolution().max(1))
+    }
+
+    /// Returns the time at which the system was booted, as the elapsed time
+    /// since the UNIX epoch
+    ///
+    /// This is derived from the current system time and `BootInstant`, and is
+    /// only meaningful on platforms where `BootInstant` counts the time since
+    /// boot (Linux, Android, macOS and Windows).
+    ///
+    /// `MockClock` controls the system time but not `BootInstant`, so while
+    /// the clocks are frozen, the result is meaningless.
+    pub fn boot_time() -> UnixTimeStamp {
+        let uptime = BootInstant::now_without_cache_update().as_u64();
+        Duration::from_u64(unix_ts().saturating_sub(uptime))
     }
 
     /// Sets the cached system time to the specified timestamp.
     /// This function is intended for testing purposes only.
     /// It should not be used in production code.
     pub fn set_recent_since_epoch(recent: UnixTimeStamp) {
-        RECENT.store(recent.as_u64(), Ordering::Relaxed)
+        GLOBAL.set_recent_since_epoch(recent);
     }
 }
 
+impl Date {
+    /// The UNIX epoch (1970-01-01 00:00:00 UTC)
+    pub const UNIX_EPOCH: Date = Date(0);
+
+    /// Creates a date from the number of seconds since the UNIX epoch
+    #[inline]
+    pub const fn from_unix_secs(secs: u64) -> Date {
+        Date(Duration::from_secs(secs).as_u64())
+    }
+
+    /// Creates a date from the number of milliseconds since the UNIX epoch
+    #[inline]
+    pub const fn from_unix_millis(millis: u64) -> Date {
+        let nanos = (millis % 1_000) as u32 * 1_000_000;
+        Date(Duration::new(millis / 1_000, nanos).as_u64())
+    }
+
+    /// Creates a date from the elapsed time since the UNIX epoch
+    #[inline]
+    pub const fn from_unix_timestamp(since_epoch: UnixTimeStamp) -> Date {
+        Date(since_epoch.as_u64())
+    }
+
+    /// Returns the number of whole seconds since the UNIX epoch
+    #[inline]
+    pub const fn as_unix_secs(&self) -> u64 {
+        self.as_unix_timestamp().as_secs()
+    }
+
+    /// Returns the number of whole milliseconds since the UNIX epoch
+    #[inline]
+    pub const fn as_unix_millis(&self) -> u64 {
+        self.as_unix_timestamp().as_millis()
+    }
+
+    /// Returns the elapsed time since the UNIX epoch
+    #[inline]
+    pub const fn as_unix_timestamp(&self) -> UnixTimeStamp {
+        Duration::from_u64(self.0)
+    }
+
+    /// Returns the amount of time elapsed from another date to this one,
+    /// or zero if that date is later than this one
+    #[inline]
+    pub fn duration_since(&self, earlier: Date) -> Duration {
+        *self - earlier
+    }
+
+    /// Returns the amount of time elapsed from another date to this one,
+    /// or `None` if that date is later than this one
+    #[inline]
+    pub fn checked_duration_since(&self, earlier: Date) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(Duration::from_u64)
+    }
+
+    /// Returns the amount of time elapsed from another date to this one,
+    /// which is negative if that date is later than this one
+    #[inline]
+    pub fn signed_duration_since(&self, earlier: Date) -> SignedDuration {
+        SignedDuration::between(self.0, earlier.0)
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub const fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub const fn from_u64(ts: u64) -> Date {
+        Date(ts)
+    }
+
+    /// Calculate a `Date` that is a `Duration` later, saturating on overflow
+    #[inline]
+    pub const fn saturating_add(self, rhs: Duration) -> Date {
+        Date(self.0.saturating_add(rhs.as_u64()))
+    }
+
+    /// Calculate a `Date` that is a `Duration` later, returning `None` on overflow
+    #[inline]
+    pub fn checked_add(self, rhs: Duration) -> Option<Date> {
+        self.0.checked_add(rhs.as_u64()).map(Date)
+    }
+
+    /// Calculate a `Date` that is a `Duration` earlier, saturating on underflow
+    #[inline]
+    pub const fn saturating_sub(self, rhs: Duration) -> Date {
+        Date(self.0.saturating_sub(rhs.as_u64()))
+    }
+
+    /// Calculate a `Date` that is a `Duration` earlier, returning `None` on underflow
+    #[inline]
+    pub fn checked_sub(self, rhs: Duration) -> Option<Date> {
+        self.0.checked_sub(rhs.as_u64()).map(Date)
+    }
+}
+
+impl Sub<Date> for Date {
+    type Output = Duration;
+
+    #[inline]
+    fn sub(self, other: Date) -> Duration {
+        Duration::from_u64(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Sub<Duration> for Date {
+    type Output = Date;
+
+    #[inline]
+    fn sub(self, rhs: Duration) -> Date {
+        Date(self.0 - rhs.as_u64())
+    }
+}
+
+impl SubAssign<Duration> for Date {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Add<Duration> for Date {
+    type Output = Date;
+
+    #[inline]
+    fn add(self, rhs: Duration) -> Date {
+        Date(self.0 + rhs.as_u64())
+    }
+}
+
+impl AddAssign<Duration> for Date {
+    #[inline]
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(not(all(
+    any(target_arch = "wasm32", target_arch = "wasm64"),
+    target_os = "unknown"
+)))]
+impl From<Date> for time::SystemTime {
+    #[inline]
+    fn from(date: Date) -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::from(date.as_unix_timestamp())
+    }
+}
+
+#[cfg(not(all(
+    any(target_arch = "wasm32", target_arch = "wasm64"),
+    target_os = "unknown"
+)))]
+impl TryFrom<time::SystemTime> for Date {
+    type Error = time::SystemTimeError;
+
+    /// Converts a `SystemTime` into a `Date`, failing if it is earlier than
+    /// the UNIX epoch
+    #[inline]
+    fn try_from(system_time: time::SystemTime) -> Result<Date, Self::Error> {
+        let since_epoch = system_time.duration_since(time::UNIX_EPOCH)?;
+        Ok(Date::from_unix_timestamp(Duration::from(since_epoch)))
+    }
+}
+
+#[inline]
+pub(crate) fn unix_ts() -> u64 {
+    #[cfg(feature = "mock")]
+    if let Some(now) = super::mock::unix_ts() {
+        return now;
+    }
+    os_unix_ts()
+}
+
+#[inline]
+fn unix_ts_precise() -> u64 {
+    #[cfg(feature = "mock")]
+    if let Some(now) = super::mock::unix_ts() {
+        return now;
+    }
+    os_unix_ts_precise()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const CLOCK_REALTIME_FAST: libc::clockid_t = libc::CLOCK_REALTIME_COARSE;
+
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+const CLOCK_REALTIME_FAST: libc::clockid_t = libc::CLOCK_REALTIME_FAST;
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+#[inline]
+fn os_unix_ts() -> u64 {
+    let mut tp = MaybeUninit::<libc::timespec>::uninit();
+    let tp = unsafe {
+        libc::clock_gettime(CLOCK_REALTIME_FAST, tp.as_mut_ptr());
+        tp.assume_init()
+    };
+    assert!(tp.tv_sec >= 0, "The system clock is not properly set");
+    _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+#[inline]
+fn os_unix_ts() -> u64 {
+    os_unix_ts_precise()
+}
+
 #[cfg(all(
     any(target_arch = "wasm32", target_arch = "wasm64"),
     target_os = "unknown"
 ))]
 #[inline]
-fn unix_ts() -> u64 {
+fn os_unix_ts_precise() -> u64 {
     let unix_ts_now_sys = (js_imports::Date::now() / 1000.0).round() as u64;
     let unix_ts_now = Duration::from_secs(unix_ts_now_sys);
     unix_ts_now.as_u64()
@@ -81,10 +415,83 @@ fn unix_ts() -> u64 {
     target_os = "unknown"
 )))]
 #[inline]
-fn unix_ts() -> u64 {
+fn os_unix_ts_precise() -> u64 {
     let unix_ts_now_sys = time::SystemTime::now()
         .duration_since(time::UNIX_EPOCH)
         .expect("The system clock is not properly set");
     let unix_ts_now = Duration::from(unix_ts_now_sys);
     unix_ts_now.as_u64()
 }
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+fn unix_ts_resolution() -> u64 {
+    let mut tp = MaybeUninit::<libc::timespec>::uninit();
+    let tp = unsafe {
+        libc::clock_getres(CLOCK_REALTIME_FAST, tp.as_mut_ptr());
+        tp.assume_init()
+    };
+    _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))
+))]
+fn unix_ts_resolution() -> u64 {
+    let mut tp = MaybeUninit::<libc::timespec>::uninit();
+    let tp = unsafe {
+        libc::clock_getres(libc::CLOCK_REALTIME, tp.as_mut_ptr());
+        tp.assume_init()
+    };
+    _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
+}
+
+#[cfg(windows)]
+fn unix_ts_resolution() -> u64 {
+    _nsecs_to_u64(100)
+}
+
+#[cfg(all(target_os = "wasi", not(feature = "wasi-abi2")))]
+fn unix_ts_resolution() -> u64 {
+    use wasix::{clock_res_get, CLOCKID_REALTIME};
+    let nsec = unsafe { clock_res_get(CLOCKID_REALTIME) }.expect("Clock not available");
+    _nsecs_to_u64(nsec)
+}
+
+#[cfg(all(target_os = "wasi", feature = "wasi-abi2"))]
+fn unix_ts_resolution() -> u64 {
+    let res = wasi_abi2::clocks::wall_clock::resolution();
+    _timespec_to_u64(res.seconds, res.nanoseconds)
+}
+
+#[cfg(all(
+    any(target_arch = "wasm32", target_arch = "wasm64"),
+    target_os = "unknown"
+))]
+fn unix_ts_resolution() -> u64 {
+    // `unix_ts()` rounds the time to the nearest second
+    _sec_to_u64(1)
+}
+
+#[cfg(not(any(
+    unix,
+    windows,
+    target_os = "wasi",
+    all(
+        any(target_arch = "wasm32", target_arch = "wasm64"),
+        target_os = "unknown"
+    )
+)))]
+fn unix_ts_resolution() -> u64 {
+    _nsecs_to_u64(1)
+}
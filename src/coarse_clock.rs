@@ -0,0 +1,233 @@
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::clock::{self, *};
+use super::duration::*;
+use super::http_date;
+use super::instant::*;
+use super::seqlock::SeqLock;
+
+// The instance behind `Instant::recent()` and `Clock::recent()`
+pub(crate) static GLOBAL: CoarseClock = CoarseClock::new();
+
+/// A set of cached clock values.
+///
+/// `Instant::recent()` and `Clock::recent()` read the process-wide default
+/// instance, returned by `CoarseClock::global()`. Independent instances can
+/// be created, so that a library can update its own cached values without
+/// interfering with the rest of the application.
+///
+/// An `Updater` can refresh any instance with a `'static` lifetime, using
+/// `UpdaterBuilder::clock()`.
+#[derive(Debug)]
+pub struct CoarseClock {
+    pub(crate) instant: AtomicU64,
+    pub(crate) since_epoch: AtomicU64,
+    // The instant and the system time, published together
+    snapshot: SeqLock<2>,
+}
+
+/// An instant and the system time, read together.
+///
+/// `Instant::update()` and `Clock::update()` refresh their cached values
+/// independently, so that `Instant::recent()` and `Clock::recent()` can be
+/// from different updates. A `TimeSnapshot` always contains values from the
+/// same update, and can be used to correlate both clocks.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TimeSnapshot {
+    instant: Instant,
+    since_epoch: UnixTimeStamp,
+}
+
+impl CoarseClock {
+    /// Creates a new instance, whose cached values have never been updated
+    pub const fn new() -> CoarseClock {
+        CoarseClock {
+            instant: AtomicU64::new(0),
+            since_epoch: AtomicU64::new(0),
+            snapshot: SeqLock::new(),
+        }
+    }
+
+    /// Returns the process-wide default instance, used by `Instant::recent()`
+    /// and `Clock::recent()`
+    #[inline]
+    pub fn global() -> &'static CoarseClock {
+        &GLOBAL
+    }
+
+    /// Returns `true` if this is the process-wide default instance
+    #[inline]
+    pub fn is_global(&self) -> bool {
+        ptr::eq(self, &GLOBAL)
+    }
+
+    /// Returns an instant corresponding to "now", and updates the cached
+    /// instant
+    pub fn now(&self) -> Instant {
+        let now = Instant::_now();
+        self.advance_instant(now);
+        Instant::from_u64(now)
+    }
+
+    /// Returns the cached instant
+    pub fn recent(&self) -> Instant {
+        match self.instant.load(Ordering::Relaxed) {
+            0 => self.now(),
+            recent => Instant::from_u64(recent),
+        }
+    }
+
+    /// Returns the elapsed time since the UNIX epoch, and updates the cached
+    /// system time
+    pub fn now_since_epoch(&self) -> UnixTimeStamp {
+        let now = clock::unix_ts();
+        self.advance_since_epoch(now);
+        Duration::from_u64(now)
+    }
+
+    /// Returns the cached elapsed time since the UNIX epoch
+    #[inline]
+    pub fn recent_since_epoch(&self) -> UnixTimeStamp {
+        match self.since_epoch.load(Ordering::Relaxed) {
+            0 => self.now_since_epoch(),
+            recent => Duration::from_u64(recent),
+        }
+    }
+
+    /// Returns the cached system time, as a date
+    #[inline]
+    pub fn recent_date(&self) -> Date {
+        Date::from_unix_timestamp(self.recent_since_epoch())
+    }
+
+    /// Sets the cached system time to the specified timestamp.
+    ///
+    /// Unlike updates, this can move the cached system time backwards.
+    pub fn set_recent_since_epoch(&self, recent: UnixTimeStamp) {
+        self.since_epoch.store(recent.as_u64(), Ordering::Relaxed);
+        if self.is_global() {
+            http_date::update(Date::from_unix_timestamp(recent));
+        }
+    }
+
+    /// Returns the cached instant and system time, from the same update
+    pub fn recent_snapshot(&self) -> TimeSnapshot {
+        match self.snapshot.read() {
+            [0, _] => self.update_snapshot(false),
+            [instant, since_epoch] => TimeSnapshot {
+                instant: Instant::from_u64(instant),
+                since_epoch: Duration::from_u64(since_epoch),
+            },
+        }
+    }
+
+    /// Updates the cached instant and system time.
+    ///
+    /// The cached values never move backwards, even if concurrent updates
+    /// complete out of order. As a consequence, if the system time is set
+    /// backwards, the cached system time doesn't change until the system
+    /// time catches up.
+    pub fn update(&self) {
+        self.update_snapshot(false);
+    }
+
+    /// Updates the cached instant
+    #[inline]
+    pub fn update_instant(&self) {
+        self.advance_instant(Instant::_now());
+    }
+
+    /// Updates the cached system time
+    #[inline]
+    pub fn update_since_epoch(&self) {
+        self.advance_since_epoch(clock::unix_ts());
+    }
+
+    /// Overwrites the cached values with the current time, even if it is
+    /// behind them
+    pub(crate) fn reset(&self) {
+        self.update_snapshot(true);
+    }
+
+    fn update_snapshot(&self, reset: bool) -> TimeSnapshot {
+        let snapshot = TimeSnapshot::now();
+        let words = [snapshot.instant.as_u64(), snapshot.since_epoch.as_u64()];
+        if reset {
+            self.instant.store(words[0], Ordering::Relaxed);
+            self.set_recent_since_epoch(snapshot.since_epoch);
+        } else {
+            self.advance_instant(words[0]);
+            self.advance_since_epoch(words[1]);
+        }
+        // If another thread is publishing at the same time, its values are
+        // just as recent
+        self.snapshot
+            .try_write_if(words, |current| reset || current[0] <= words[0]);
+        snapshot
+    }
+
+    // A thread that read the clock earlier must not overwrite a newer value
+    #[inline]
+    pub(crate) fn advance_instant(&self, now: u64) {
+        self.instant.fetch_max(now, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn advance_since_epoch(&self, now: u64) {
+        let previous = self.since_epoch.fetch_max(now, Ordering::Relaxed);
+        if now >= previous && self.is_global() {
+            http_date::update(Date::from_unix_timestamp(Duration::from_u64(now)));
+        }
+    }
+}
+
+impl Default for CoarseClock {
+    fn default() -> CoarseClock {
+        CoarseClock::new()
+    }
+}
+
+impl TimeSnapshot {
+    /// Returns the instant and the system time of the latest update of both
+    /// cached values.
+    ///
+    /// The snapshot is refreshed by `TimeSnapshot::update()`, and by an
+    /// `Updater` refreshing both caches.
+    #[inline]
+    pub fn recent() -> TimeSnapshot {
+        GLOBAL.recent_snapshot()
+    }
+
+    /// Returns the current instant and system time
+    pub fn now() -> TimeSnapshot {
+        TimeSnapshot {
+            instant: Instant::from_u64(Instant::_now()),
+            since_epoch: Duration::from_u64(clock::unix_ts()),
+        }
+    }
+
+    /// Updates the cached instant and system time, as well as the snapshot
+    #[inline]
+    pub fn update() {
+        GLOBAL.update()
+    }
+
+    /// Returns the instant
+    #[inline]
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    /// Returns the elapsed time since the UNIX epoch
+    #[inline]
+    pub fn since_epoch(&self) -> UnixTimeStamp {
+        self.since_epoch
+    }
+
+    /// Returns the system time, as a date
+    #[inline]
+    pub fn date(&self) -> Date {
+        Date::from_unix_timestamp(self.since_epoch)
+    }
+}
@@ -1,16 +1,39 @@
 use std::convert::From;
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::iter::Sum;
 use std::ops::*;
+use std::str::FromStr;
 use std::time;
 
 use super::helpers::*;
 
+const MAX_NANOS: u128 = (1 << 32) * 1_000_000_000;
+
 /// A duration type to represent an approximate span of time
-#[derive(Copy, Clone, Debug, Hash, Ord, Eq, PartialOrd, PartialEq, Default)]
+///
+/// Durations are displayed as days, hours, minutes and seconds, such as
+/// `1h 2m 3.250s`. The precision sets the number of fractional digits, and
+/// defaults to milliseconds. The alternate form (`{:#}`) shows nanoseconds,
+/// without trailing zeros.
+///
+/// Durations can be parsed from the same representation, as well as from
+/// more compact strings such as `250ms`, `1h30m` or `2.5s`.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq, Default)]
 pub struct Duration(u64);
 
 impl Duration {
+    /// A duration of zero time
+    pub const ZERO: Duration = Duration(0);
+
+    /// The maximum duration, slightly less than 2^32 seconds
+    pub const MAX: Duration = Duration(u64::MAX);
+
     /// Creates a new `Duration` from the specified number of seconds and
-    /// additional nanosecond precision
+    /// additional nanosecond precision.
+    ///
+    /// The representation has a resolution of 2^-32 seconds (about 0.23
+    /// nanoseconds), and the nanoseconds are rounded down to it.
     #[inline]
     pub const fn new(sec: u64, nanos: u32) -> Duration {
         Duration(_timespec_to_u64(sec, nanos))
@@ -40,12 +63,63 @@ impl Duration {
         Duration(_sec_to_u64(secs))
     }
 
-    /// Creates a new Duration from the specified number of milliseconds
+    /// Creates a new Duration from the specified number of milliseconds.
+    ///
+    /// For speed, the fractional part of the duration is computed as if a
+    /// second had 1024 milliseconds, so the result can be up to 2.4% shorter
+    /// than the requested duration: `from_millis(1500)` is about 1.488
+    /// seconds. Whole seconds are exact. Use `new()`, `from_micros()` or
+    /// `from_nanos()` when accuracy matters.
     #[inline]
     pub const fn from_millis(millis: u64) -> Duration {
         Duration(_millis_to_u64(millis))
     }
 
+    /// Creates a new Duration from the specified number of microseconds,
+    /// rounded down to the resolution of the representation
+    #[inline]
+    pub const fn from_micros(micros: u64) -> Duration {
+        let secs = micros / 1_000_000;
+        Duration(_timespec_to_u64(
+            secs,
+            ((micros - secs * 1_000_000) * 1_000) as u32,
+        ))
+    }
+
+    /// Creates a new Duration from the specified number of nanoseconds,
+    /// rounded down to the resolution of the representation
+    #[inline]
+    pub const fn from_nanos(nanos: u64) -> Duration {
+        Duration(_nsecs_to_u64(nanos))
+    }
+
+    /// Creates a new Duration from a floating point number of seconds,
+    /// rounded to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secs` is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn from_secs_f64(secs: f64) -> Duration {
+        Self::from_ticks_f64(secs * (1u64 << 32) as f64)
+    }
+
+    /// Creates a new Duration from a floating point number of seconds,
+    /// rounded to the nearest representable value.
+    ///
+    /// Since an `f32` only has 24 bits of precision, durations above a few
+    /// seconds are not accurate to the nanosecond.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secs` is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn from_secs_f32(secs: f32) -> Duration {
+        Self::from_secs_f64(secs as f64)
+    }
+
     /// Returns the number of days represented by this duration
     #[inline]
     pub const fn as_days(&self) -> u64 {
@@ -133,6 +207,97 @@ impl Duration {
         (self.0 as f64) / ((1u64 << 32) as f64)
     }
 
+    /// Returns the number of seconds as an `f64`.
+    ///
+    /// This is the same as `as_f64()`. Durations longer than 2^21 seconds
+    /// (about 24 days) lose their sub-nanosecond part.
+    #[inline]
+    pub fn as_secs_f64(&self) -> f64 {
+        self.as_f64()
+    }
+
+    /// Returns the number of seconds as an `f32`, rounded to the nearest
+    /// representable value
+    #[inline]
+    pub fn as_secs_f32(&self) -> f32 {
+        self.as_f64() as f32
+    }
+
+    /// Returns `true` if this duration is zero
+    #[inline]
+    pub const fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Multiplies a duration by a floating point number, rounding the result
+    /// to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn mul_f64(self, rhs: f64) -> Duration {
+        Self::from_ticks_f64(self.0 as f64 * rhs)
+    }
+
+    /// Multiplies a duration by a floating point number, rounding the result
+    /// to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn mul_f32(self, rhs: f32) -> Duration {
+        self.mul_f64(rhs as f64)
+    }
+
+    /// Divides a duration by a floating point number, rounding the result
+    /// to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn div_f64(self, rhs: f64) -> Duration {
+        Self::from_ticks_f64(self.0 as f64 / rhs)
+    }
+
+    /// Divides a duration by a floating point number, rounding the result
+    /// to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is negative, not finite, or doesn't fit in a
+    /// `Duration`.
+    #[inline]
+    pub fn div_f32(self, rhs: f32) -> Duration {
+        self.div_f64(rhs as f64)
+    }
+
+    /// Divides a duration by another one, as a floating point number
+    #[inline]
+    pub fn div_duration_f64(self, rhs: Duration) -> f64 {
+        self.0 as f64 / rhs.0 as f64
+    }
+
+    /// Divides a duration by another one, as a floating point number
+    #[inline]
+    pub fn div_duration_f32(self, rhs: Duration) -> f32 {
+        self.div_duration_f64(rhs) as f32
+    }
+
+    fn from_ticks_f64(ticks: f64) -> Duration {
+        // `u64::MAX as f64` is 2^64, which is out of range
+        assert!(
+            ticks >= 0.0 && ticks < u64::MAX as f64,
+            "cannot convert float seconds to Duration: value is negative, not finite or too large"
+        );
+        Duration(ticks.round() as u64)
+    }
+
     /// Returns the absolute difference between two `Duration`s
     #[inline]
     pub const fn abs_diff(&self, other: Duration) -> Duration {
@@ -180,6 +345,142 @@ impl Duration {
     pub fn checked_div(self, rhs: u32) -> Option<Duration> {
         self.0.checked_div(rhs as u64).map(Duration)
     }
+
+    /// Returns the remainder of the division of a duration by another one,
+    /// or `None` if `rhs` is zero
+    #[inline]
+    pub fn checked_rem(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_rem(rhs.0).map(Duration)
+    }
+
+    /// Rounds the duration down to a multiple of `granularity`.
+    ///
+    /// The result is an exact multiple of the granularity as represented
+    /// internally. `Duration::new(0, 100_000_000)` is less than 0.24
+    /// nanoseconds short of 100 milliseconds, so its n-th multiple is less
+    /// than n * 0.24 nanoseconds off. But granularities built with
+    /// `Duration::from_millis()` inherit its approximation:
+    /// `Duration::from_millis(100)` is about 97.66 milliseconds, and so are
+    /// the resulting buckets, 2.3% shorter than expected.
+    ///
+    /// Granularities that are a power of two of the internal representation,
+    /// such as whole seconds or `Duration::from_millis(512)`, only require
+    /// masking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero.
+    #[inline]
+    pub fn truncate_to(self, granularity: Duration) -> Duration {
+        Duration(self.0 - _rem_ticks(self.0, granularity.0))
+    }
+
+    /// Rounds the duration up to a multiple of `granularity`.
+    ///
+    /// See `truncate_to()` for the accuracy of millisecond granularities.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero, or if the result overflows.
+    #[inline]
+    pub fn ceil_to(self, granularity: Duration) -> Duration {
+        match _rem_ticks(self.0, granularity.0) {
+            0 => self,
+            rem => Duration(
+                (self.0 - rem)
+                    .checked_add(granularity.0)
+                    .expect("overflow when rounding duration"),
+            ),
+        }
+    }
+
+    /// Rounds the duration to the nearest multiple of `granularity`, with
+    /// halfway values rounded up.
+    ///
+    /// See `truncate_to()` for the accuracy of millisecond granularities.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero, or if the result overflows.
+    #[inline]
+    pub fn round_to(self, granularity: Duration) -> Duration {
+        let rem = _rem_ticks(self.0, granularity.0);
+        if rem < granularity.0 - rem {
+            Duration(self.0 - rem)
+        } else {
+            self.ceil_to(granularity)
+        }
+    }
+}
+
+impl Duration {
+    /// Formats the duration, with a minus sign if `negative` is set.
+    ///
+    /// Without an explicit precision, the `Debug` and alternate forms show
+    /// nanoseconds without trailing zeros, and the regular form milliseconds.
+    pub(crate) fn fmt_human(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        negative: bool,
+        debug: bool,
+    ) -> fmt::Result {
+        let mut buf = FmtBuf::<48>::new();
+        if negative {
+            buf.write_char('-')?;
+        }
+        match f.precision() {
+            Some(precision) => self.write_human(&mut buf, precision, false)?,
+            None if debug || f.alternate() => self.write_human(&mut buf, 9, true)?,
+            None => self.write_human(&mut buf, 3, false)?,
+        }
+        _pad(f, buf.as_str())
+    }
+
+    /// Writes the duration rounded to `precision` fractional digits,
+    /// optionally without trailing zeros
+    fn write_human<W: Write>(&self, w: &mut W, precision: usize, trim: bool) -> fmt::Result {
+        let precision = precision.min(9);
+        let unit = 10u128.pow(9 - precision as u32);
+        // Round to the nearest nanosecond, then to the requested precision
+        let nanos = ((self.0 as u128 * 1_000_000_000) + (1 << 31)) >> 32;
+        let nanos = (nanos + unit / 2) / unit * unit;
+        let secs = (nanos / 1_000_000_000) as u64;
+        let (days, hours, mins) = (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60);
+        if days > 0 {
+            write!(w, "{}d ", days)?;
+        }
+        if days > 0 || hours > 0 {
+            write!(w, "{}h ", hours)?;
+        }
+        if days > 0 || hours > 0 || mins > 0 {
+            write!(w, "{}m ", mins)?;
+        }
+        write!(w, "{}", secs % 60)?;
+        let mut frac = FmtBuf::<9>::new();
+        if precision > 0 {
+            write!(frac, "{:01$}", nanos % 1_000_000_000 / unit, precision)?;
+        }
+        let frac = match trim {
+            true => frac.as_str().trim_end_matches('0'),
+            false => frac.as_str(),
+        };
+        if !frac.is_empty() {
+            write!(w, ".{}", frac)?;
+        }
+        w.write_char('s')
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_human(f, false, false)
+    }
+}
+
+impl fmt::Debug for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_human(f, false, true)
+    }
 }
 
 #[doc(hidden)]
@@ -239,6 +540,15 @@ impl MulAssign<u32> for Duration {
     }
 }
 
+impl Mul<Duration> for u32 {
+    type Output = Duration;
+
+    #[inline]
+    fn mul(self, rhs: Duration) -> Duration {
+        rhs * self
+    }
+}
+
 impl Div<u32> for Duration {
     type Output = Duration;
 
@@ -255,6 +565,34 @@ impl DivAssign<u32> for Duration {
     }
 }
 
+impl Rem for Duration {
+    type Output = Duration;
+
+    #[inline]
+    fn rem(self, rhs: Duration) -> Duration {
+        Duration(self.0 % rhs.0)
+    }
+}
+
+impl RemAssign for Duration {
+    #[inline]
+    fn rem_assign(&mut self, rhs: Duration) {
+        *self = *self % rhs;
+    }
+}
+
+impl Sum for Duration {
+    fn sum<I: Iterator<Item = Duration>>(iter: I) -> Duration {
+        iter.fold(Duration::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Duration> for Duration {
+    fn sum<I: Iterator<Item = &'a Duration>>(iter: I) -> Duration {
+        iter.copied().sum()
+    }
+}
+
 impl From<Duration> for time::Duration {
     #[inline]
     fn from(duration: Duration) -> time::Duration {
@@ -268,3 +606,146 @@ impl From<time::Duration> for Duration {
         Duration::new(duration_sys.as_secs(), duration_sys.subsec_nanos())
     }
 }
+
+/// The reason why a duration could not be parsed
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ParseDurationErrorKind {
+    /// The string is empty
+    Empty,
+    /// A number was expected
+    InvalidNumber,
+    /// A number is not followed by a unit
+    MissingUnit,
+    /// The unit is not one of `ns`, `us`, `µs`, `ms`, `s`, `m`, `h` and `d`
+    UnknownUnit,
+    /// The duration is too large to be represented
+    Overflow,
+}
+
+/// An error returned when a duration cannot be parsed
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ParseDurationError {
+    kind: ParseDurationErrorKind,
+    position: usize,
+}
+
+impl ParseDurationError {
+    /// Returns the reason why the duration could not be parsed
+    #[inline]
+    pub fn kind(&self) -> ParseDurationErrorKind {
+        self.kind
+    }
+
+    /// Returns the byte offset of the error in the parsed string
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.kind {
+            ParseDurationErrorKind::Empty => "empty string",
+            ParseDurationErrorKind::InvalidNumber => "expected a number",
+            ParseDurationErrorKind::MissingUnit => "missing unit",
+            ParseDurationErrorKind::UnknownUnit => "unknown unit",
+            ParseDurationErrorKind::Overflow => "duration too large",
+        };
+        write!(
+            f,
+            "invalid duration: {} at offset {}",
+            reason, self.position
+        )
+    }
+}
+
+impl Error for ParseDurationError {}
+
+impl FromStr for Duration {
+    type Err = ParseDurationError;
+
+    /// Parses a sequence of numbers followed by units, such as `1h30m`,
+    /// `2.5s` or `1h 2m 3.250s`.
+    ///
+    /// The supported units are `ns`, `us` (or `µs`), `ms`, `s`, `m`, `h` and
+    /// `d`. Numbers can have a fractional part, and a single `0` doesn't
+    /// require a unit.
+    fn from_str(s: &str) -> Result<Duration, ParseDurationError> {
+        let error = |kind, position| ParseDurationError { kind, position };
+        let bytes = s.as_bytes();
+        let skip_spaces = |mut pos: usize| {
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            pos
+        };
+        let mut pos = skip_spaces(0);
+        if pos == bytes.len() {
+            return Err(error(ParseDurationErrorKind::Empty, pos));
+        }
+        if s[pos..].trim_end() == "0" {
+            return Ok(Duration(0));
+        }
+        let mut total: u128 = 0;
+        while pos < bytes.len() {
+            let start = pos;
+            let (mut int, mut frac, mut frac_scale) = (0u128, 0u128, 1u128);
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                int = int * 10 + (bytes[pos] - b'0') as u128;
+                if int > MAX_NANOS {
+                    return Err(error(ParseDurationErrorKind::Overflow, start));
+                }
+                pos += 1;
+            }
+            let int_digits = pos - start;
+            let mut frac_digits = 0;
+            if pos < bytes.len() && bytes[pos] == b'.' {
+                pos += 1;
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    // Digits beyond nanoseconds of a day are insignificant
+                    if frac_scale < 1_000_000_000_000_000_000 {
+                        frac = frac * 10 + (bytes[pos] - b'0') as u128;
+                        frac_scale *= 10;
+                    }
+                    frac_digits += 1;
+                    pos += 1;
+                }
+            }
+            if int_digits == 0 && frac_digits == 0 {
+                return Err(error(ParseDurationErrorKind::InvalidNumber, start));
+            }
+            pos = skip_spaces(pos);
+            let unit_start = pos;
+            let unit_len = s[pos..]
+                .find(|c: char| !c.is_alphabetic())
+                .unwrap_or(s.len() - pos);
+            pos += unit_len;
+            let unit_nanos: u128 = match &s[unit_start..pos] {
+                "" => return Err(error(ParseDurationErrorKind::MissingUnit, unit_start)),
+                "ns" => 1,
+                "us" | "µs" => 1_000,
+                "ms" => 1_000_000,
+                "s" => 1_000_000_000,
+                "m" => 60_000_000_000,
+                "h" => 3_600_000_000_000,
+                "d" => 86_400_000_000_000,
+                _ => return Err(error(ParseDurationErrorKind::UnknownUnit, unit_start)),
+            };
+            total += int * unit_nanos + frac * unit_nanos / frac_scale;
+            if total > MAX_NANOS {
+                return Err(error(ParseDurationErrorKind::Overflow, start));
+            }
+            pos = skip_spaces(pos);
+        }
+        // `Duration::MAX` is displayed rounded up to 2^32 seconds
+        if total == MAX_NANOS {
+            return Ok(Duration::MAX);
+        }
+        Ok(Duration::new(
+            (total / 1_000_000_000) as u64,
+            (total % 1_000_000_000) as u32,
+        ))
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::{self, Write};
+
 #[inline]
 pub const fn _sec_to_u64(sec: u64) -> u64 {
     sec.saturating_mul(1 << 32)
@@ -24,3 +26,305 @@ pub const fn _timespec_to_u64(tp_sec: u64, tp_nsec: u32) -> u64 {
 pub const fn _timeval_to_u64(tv_sec: u64, tv_usec: u32) -> u64 {
     tv_sec.saturating_mul(1 << 32) | ((tv_usec as u64 * 9_223_372_036_855) >> 31)
 }
+
+// Masking is much cheaper than a division, and power-of-two granularities
+// are common with a binary fixed-point representation
+#[inline]
+pub const fn _rem_ticks(ticks: u64, unit: u64) -> u64 {
+    if unit.is_power_of_two() {
+        ticks & (unit - 1)
+    } else {
+        ticks % unit
+    }
+}
+
+#[inline]
+pub const fn _days_to_civil(days: u64) -> (u64, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+#[inline]
+pub const fn _civil_to_days(year: u32, month: u32, day: u32) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[inline]
+pub const fn _days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A fixed-size buffer to format short strings without allocating
+pub(crate) struct FmtBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FmtBuf<N> {
+    pub(crate) const fn new() -> Self {
+        FmtBuf {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // Only complete string slices are ever copied into the buffer
+        std::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Writes `s`, honoring the width, fill and alignment of the formatter.
+///
+/// Unlike `Formatter::pad()`, this doesn't truncate `s` to the precision,
+/// which the callers use for the number of fractional digits instead.
+pub(crate) fn _pad(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    let len = s.chars().count();
+    let padding = f.width().unwrap_or(0).saturating_sub(len);
+    let (pre, post) = match f.align() {
+        Some(fmt::Alignment::Right) => (padding, 0),
+        Some(fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+        _ => (0, padding),
+    };
+    let fill = f.fill();
+    for _ in 0..pre {
+        f.write_char(fill)?;
+    }
+    f.write_str(s)?;
+    for _ in 0..post {
+        f.write_char(fill)?;
+    }
+    Ok(())
+}
+
+/// Implements the conversions, arithmetic and formatting shared by the
+/// monotonic clocks, for a `struct $name(u64)`.
+macro_rules! impl_instant {
+    ($name:ident, $a:literal) => {
+        impl $name {
+            /// Returns the amount of time elapsed from another instant to this one
+            #[inline]
+            pub fn duration_since(&self, earlier: $name) -> $crate::Duration {
+                *self - earlier
+            }
+
+            /// Return a representation of this instant as a number of "ticks".
+            ///
+            /// Note that length of a 'tick' is not guaranteed to represent
+            /// the same amount of time across different platforms, or from
+            /// one version of `coarsetime` to another.
+            ///
+            /// Note also that the instant represented by "0" ticks is
+            /// unspecified.  It is not guaranteed to be the same time across
+            /// different platforms, or from one version of `coarsetime` to
+            /// another.
+            ///
+            /// This API is mainly intended for applications that need to
+            #[doc = concat!("store the value of ", $a, " `", stringify!($name), "` in an")]
+            /// [`AtomicU64`](std::sync::atomic::AtomicU64).
+            #[inline]
+            pub const fn as_ticks(&self) -> u64 {
+                self.as_u64()
+            }
+
+            #[doc = concat!("Create ", $a, " `", stringify!($name), "` from a number of \"ticks\".")]
+            ///
+            /// Note that length of a 'tick' is not guaranteed to represent
+            /// the same amount of time across different platforms, or from
+            /// one version of `coarsetime` to another.
+            ///
+            /// Note also that the instant represented by "0" ticks is
+            /// unspecified.  It is not guaranteed to be the same time across
+            /// different platforms, or from one version of `coarsetime` to
+            /// another.
+            #[inline]
+            pub const fn from_ticks(ticks: u64) -> $name {
+                Self::from_u64(ticks)
+            }
+
+            #[doc(hidden)]
+            #[inline]
+            pub const fn as_u64(&self) -> u64 {
+                self.0
+            }
+
+            #[doc(hidden)]
+            #[inline]
+            pub const fn from_u64(ts: u64) -> $name {
+                $name(ts)
+            }
+
+            #[doc = concat!("Calculate ", $a, " `", stringify!($name), "` that is a `Duration` later, saturating on overflow")]
+            #[inline]
+            pub const fn saturating_add(self, rhs: $crate::Duration) -> $name {
+                $name(self.0.saturating_add(rhs.as_u64()))
+            }
+
+            #[doc = concat!("Calculate ", $a, " `", stringify!($name), "` that is a `Duration` later, returning `None` on overflow")]
+            #[inline]
+            pub fn checked_add(self, rhs: $crate::Duration) -> Option<$name> {
+                self.0.checked_add(rhs.as_u64()).map($name)
+            }
+
+            #[doc = concat!("Calculate ", $a, " `", stringify!($name), "` that is a `Duration` earlier, saturating on underflow")]
+            #[inline]
+            pub const fn saturating_sub(self, rhs: $crate::Duration) -> $name {
+                $name(self.0.saturating_sub(rhs.as_u64()))
+            }
+
+            #[doc = concat!("Calculate ", $a, " `", stringify!($name), "` that is a `Duration` earlier, returning `None` on underflow")]
+            #[inline]
+            pub fn checked_sub(self, rhs: $crate::Duration) -> Option<$name> {
+                self.0.checked_sub(rhs.as_u64()).map($name)
+            }
+        }
+
+        impl ::std::ops::Sub<$name> for $name {
+            type Output = $crate::Duration;
+
+            #[inline]
+            fn sub(self, other: $name) -> $crate::Duration {
+                $crate::Duration::from_u64(self.0.saturating_sub(other.0))
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                ::std::fmt::Display::fmt(&$crate::Duration::from_u64(self.0), f)
+            }
+        }
+
+        impl ::std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.debug_tuple(stringify!($name))
+                    .field(&$crate::Duration::from_u64(self.0))
+                    .finish()
+            }
+        }
+
+        impl ::std::ops::Sub<$crate::Duration> for $name {
+            type Output = $name;
+
+            #[inline]
+            fn sub(self, rhs: $crate::Duration) -> $name {
+                $name(self.0 - rhs.as_u64())
+            }
+        }
+
+        impl ::std::ops::SubAssign<$crate::Duration> for $name {
+            #[inline]
+            fn sub_assign(&mut self, rhs: $crate::Duration) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl ::std::ops::Add<$crate::Duration> for $name {
+            type Output = $name;
+
+            #[inline]
+            fn add(self, rhs: $crate::Duration) -> $name {
+                $name(self.0 + rhs.as_u64())
+            }
+        }
+
+        impl ::std::ops::AddAssign<$crate::Duration> for $name {
+            #[inline]
+            fn add_assign(&mut self, rhs: $crate::Duration) {
+                *self = *self + rhs;
+            }
+        }
+    };
+}
+
+/// Implements the API shared by the cached monotonic clocks, for a
+/// `struct $name(u64)` providing `_now()` and `_update()`, and a `recent()`
+/// function.
+macro_rules! impl_cached_instant {
+    ($name:ident, $a:literal) => {
+        $crate::helpers::impl_instant!($name, $a);
+
+        impl $name {
+            /// Returns an instant corresponding to "now"
+            ///
+            /// This function also updates the stored instant.
+            pub fn now() -> $name {
+                let now = Self::_now();
+                Self::_update(now);
+                $name(now)
+            }
+
+            /// Returns an instant corresponding to "now" without updating the cached value.
+            /// After this, `recent()` will still return the old instant.
+            ///
+            /// `now()` is generally preferred over this function.
+            pub fn now_without_cache_update() -> $name {
+                let now = Self::_now();
+                $name(now)
+            }
+
+            /// Update the stored instant
+            ///
+            /// This function should be called frequently, for example in an event loop
+            /// or using an `Updater` task.
+            pub fn update() {
+                let now = Self::_now();
+                Self::_update(now);
+            }
+
+            /// Returns the amount of time elapsed between the time this instant was
+            /// created and the latest update
+            #[inline]
+            pub fn elapsed_since_recent(&self) -> $crate::Duration {
+                Self::recent() - *self
+            }
+
+            /// Returns the amount of time elapsed since this instant was created
+            ///
+            /// This function also updates the stored instant.
+            #[inline]
+            pub fn elapsed(&self) -> $crate::Duration {
+                Self::now() - *self
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                Self::now()
+            }
+        }
+    };
+}
+
+pub(crate) use impl_cached_instant;
+pub(crate) use impl_instant;
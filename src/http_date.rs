@@ -0,0 +1,255 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::clock::*;
+use super::duration::*;
+use super::helpers::*;
+use super::rfc3339::{get_digits, put_digits};
+use super::seqlock::SeqLock;
+
+const HTTP_DATE_LEN: usize = 29;
+
+const DAY_NAMES: [&[u8; 3]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+
+const LONG_DAY_NAMES: [&[u8]; 7] = [
+    b"Sunday",
+    b"Monday",
+    b"Tuesday",
+    b"Wednesday",
+    b"Thursday",
+    b"Friday",
+    b"Saturday",
+];
+
+const MONTH_NAMES: [&[u8; 3]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+// The rendered date, packed into 4 words, and the second it was rendered for
+static CACHE: SeqLock<4> = SeqLock::new();
+static CACHE_SECS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// A date in the IMF-fixdate format used by HTTP headers, such as
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// The representation is stored inline, so that it can be copied and
+/// written to a response without any allocations.
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct HttpDate([u8; HTTP_DATE_LEN]);
+
+impl HttpDate {
+    /// Returns the date of the latest explicit time update.
+    ///
+    /// The representation is only re-rendered by `Clock::update()` (and thus
+    /// by the `Updater`) when the second changes, so this is just a copy of a
+    /// cached value.
+    pub fn recent() -> HttpDate {
+        if CACHE_SECS.load(Ordering::Acquire) == u64::MAX {
+            Clock::update();
+        }
+        HttpDate::from_words(CACHE.read())
+    }
+
+    /// Returns the current date
+    #[inline]
+    pub fn now() -> HttpDate {
+        HttpDate::from(Clock::now())
+    }
+
+    /// Returns the date as a string slice
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // The buffer only contains ASCII characters
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    /// Returns the date as a byte array
+    #[inline]
+    pub const fn as_bytes(&self) -> &[u8; HTTP_DATE_LEN] {
+        &self.0
+    }
+
+    /// Parses an HTTP date, such as the value of an `If-Modified-Since` header.
+    ///
+    /// The preferred IMF-fixdate format (`Sun, 06 Nov 1994 08:49:37 GMT`) is
+    /// accepted, as well as the obsolete RFC 850 (`Sunday, 06-Nov-94 08:49:37 GMT`)
+    /// and asctime (`Sun Nov  6 08:49:37 1994`) formats. Two-digit RFC 850
+    /// years before 70 are assumed to be in the 21st century.
+    pub fn parse(s: &str) -> Result<Date, ParseDateError> {
+        let s = s.as_bytes();
+        parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850(s))
+            .or_else(|| parse_asctime(s))
+            .ok_or(ParseDateError(()))
+    }
+
+    fn from_words(words: [u64; 4]) -> HttpDate {
+        let mut buf = [0u8; 32];
+        for (chunk, word) in buf.chunks_exact_mut(8).zip(words.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let mut date = [0u8; HTTP_DATE_LEN];
+        date.copy_from_slice(&buf[..HTTP_DATE_LEN]);
+        HttpDate(date)
+    }
+
+    fn to_words(self) -> [u64; 4] {
+        let mut buf = [0u8; 32];
+        buf[..HTTP_DATE_LEN].copy_from_slice(&self.0);
+        let mut words = [0u64; 4];
+        for (word, chunk) in words.iter_mut().zip(buf.chunks_exact(8)) {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            *word = u64::from_le_bytes(bytes);
+        }
+        words
+    }
+}
+
+/// Re-renders the cached representation if the second has changed
+pub(crate) fn update(date: Date) {
+    let secs = date.as_unix_secs();
+    if CACHE_SECS.load(Ordering::Relaxed) != secs
+        && CACHE.try_write(HttpDate::from(date).to_words())
+    {
+        CACHE_SECS.store(secs, Ordering::Release);
+    }
+}
+
+impl From<Date> for HttpDate {
+    fn from(date: Date) -> HttpDate {
+        let secs = date.as_unix_secs();
+        let days = secs / 86_400;
+        let (year, month, day) = _days_to_civil(days);
+        let secs_of_day = secs % 86_400;
+        let mut buf = *b"Thu, 01 Jan 1970 00:00:00 GMT";
+        buf[0..3].copy_from_slice(DAY_NAMES[((days + 4) % 7) as usize]);
+        put_digits(&mut buf[5..7], day as u64);
+        buf[8..11].copy_from_slice(MONTH_NAMES[month as usize - 1]);
+        put_digits(&mut buf[12..16], year);
+        put_digits(&mut buf[17..19], secs_of_day / 3_600);
+        put_digits(&mut buf[20..22], secs_of_day / 60 % 60);
+        put_digits(&mut buf[23..25], secs_of_day % 60);
+        HttpDate(buf)
+    }
+}
+
+impl Deref for HttpDate {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for HttpDate {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<[u8]> for HttpDate {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl fmt::Debug for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+fn month_from_name(name: &[u8]) -> Option<u32> {
+    MONTH_NAMES
+        .iter()
+        .position(|&month| month == name)
+        .map(|month| month as u32 + 1)
+}
+
+fn to_date(year: u32, month: u32, day: u32, time: &[u8]) -> Option<Date> {
+    if time.len() != 8 || time[2] != b':' || time[5] != b':' {
+        return None;
+    }
+    let hour = get_digits(&time[0..2])?;
+    let min = get_digits(&time[3..5])?;
+    let sec = get_digits(&time[6..8])?;
+    if day < 1 || day > _days_in_month(year, month) || hour > 23 || min > 59 || sec > 59 {
+        return None;
+    }
+    let days = u64::try_from(_civil_to_days(year, month, day)).ok()?;
+    let secs = days * 86_400 + (hour * 3_600 + min * 60 + sec) as u64;
+    if secs >= 1 << 32 {
+        return None;
+    }
+    Some(Date::from_unix_timestamp(Duration::from_secs(secs)))
+}
+
+// Sun, 06 Nov 1994 08:49:37 GMT
+fn parse_imf_fixdate(s: &[u8]) -> Option<Date> {
+    if s.len() != HTTP_DATE_LEN
+        || !DAY_NAMES.iter().any(|&name| name == &s[0..3])
+        || &s[3..5] != b", "
+        || s[7] != b' '
+        || s[11] != b' '
+        || s[16] != b' '
+        || &s[25..] != b" GMT"
+    {
+        return None;
+    }
+    let day = get_digits(&s[5..7])?;
+    let month = month_from_name(&s[8..11])?;
+    let year = get_digits(&s[12..16])?;
+    to_date(year, month, day, &s[17..25])
+}
+
+// Sunday, 06-Nov-94 08:49:37 GMT
+fn parse_rfc850(s: &[u8]) -> Option<Date> {
+    let name = LONG_DAY_NAMES.iter().find(|&&name| s.starts_with(name))?;
+    let s = &s[name.len()..];
+    if s.len() != 24
+        || &s[0..2] != b", "
+        || s[4] != b'-'
+        || s[8] != b'-'
+        || s[11] != b' '
+        || &s[20..] != b" GMT"
+    {
+        return None;
+    }
+    let day = get_digits(&s[2..4])?;
+    let month = month_from_name(&s[5..8])?;
+    let year = get_digits(&s[9..11])?;
+    let year = if year < 70 { 2000 + year } else { 1900 + year };
+    to_date(year, month, day, &s[12..20])
+}
+
+// Sun Nov  6 08:49:37 1994
+fn parse_asctime(s: &[u8]) -> Option<Date> {
+    if s.len() != 24
+        || !DAY_NAMES.iter().any(|&name| name == &s[0..3])
+        || s[3] != b' '
+        || s[7] != b' '
+        || s[10] != b' '
+        || s[19] != b' '
+    {
+        return None;
+    }
+    let month = month_from_name(&s[4..7])?;
+    let day = match s[8] {
+        b' ' => get_digits(&s[9..10])?,
+        _ => get_digits(&s[8..10])?,
+    };
+    let year = get_digits(&s[20..24])?;
+    to_date(year, month, day, &s[11..19])
+}
@@ -1,13 +1,14 @@
 #[allow(unused_imports)]
 use std::mem::MaybeUninit;
-use std::ops::*;
 #[allow(unused_imports)]
 use std::ptr::*;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU8, Ordering};
 
+use super::coarse_clock::GLOBAL;
 use super::duration::*;
-#[allow(unused_imports)]
 use super::helpers::*;
+use super::refresh;
+use super::signed_duration::*;
 
 /// A measurement of a *monotonically* increasing clock.
 /// Opaque and useful only with `Duration`.
@@ -15,26 +16,108 @@ use super::helpers::*;
 /// Resulting durations are actual durations; they do not get affected by
 /// clock adjustments, leap seconds, or similar.
 /// In order to get a measurement of the *wall clock*, use `Date` instead.
-#[derive(Copy, Clone, Debug, Hash, Ord, Eq, PartialOrd, PartialEq)]
+///
+/// Instants are displayed as the time elapsed since an unspecified origin.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq)]
 pub struct Instant(u64);
 
-static RECENT: AtomicU64 = AtomicU64::new(0);
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Coarse as u8);
+
+/// The operating system clock used to compute instants.
+///
+/// The clock source is a process-wide setting, honored by `Instant::now()`,
+/// `Instant::update()` and the `Updater`.
+///
+/// Clocks are mapped to the closest equivalent on each platform. On Linux
+/// and Android, all sources are available. On macOS, FreeBSD and DragonFly,
+/// sources without a direct equivalent fall back to the regular monotonic
+/// clock. On other platforms, the setting is ignored.
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ClockSource {
+    /// A fast, coarse monotonic clock (`CLOCK_MONOTONIC_COARSE` on Linux).
+    /// This is the default.
+    #[default]
+    Coarse = 0,
+    /// The precise monotonic clock (`CLOCK_MONOTONIC`).
+    Monotonic = 1,
+    /// A monotonic clock that is not subject to frequency adjustments
+    /// (`CLOCK_MONOTONIC_RAW`).
+    MonotonicRaw = 2,
+    /// A monotonic clock that keeps running while the system is suspended
+    /// (`CLOCK_BOOTTIME`).
+    Boottime = 3,
+}
+
+impl ClockSource {
+    #[inline]
+    fn current() -> ClockSource {
+        match CLOCK_SOURCE.load(Ordering::Relaxed) {
+            1 => ClockSource::Monotonic,
+            2 => ClockSource::MonotonicRaw,
+            3 => ClockSource::Boottime,
+            _ => ClockSource::Coarse,
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[inline]
+    fn clock_id(self) -> libc::clockid_t {
+        match self {
+            ClockSource::Coarse => libc::CLOCK_MONOTONIC_COARSE,
+            ClockSource::Monotonic => libc::CLOCK_MONOTONIC,
+            ClockSource::MonotonicRaw => libc::CLOCK_MONOTONIC_RAW,
+            ClockSource::Boottime => libc::CLOCK_BOOTTIME,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[inline]
+    fn clock_id(self) -> clockid_t {
+        match self {
+            ClockSource::Coarse => CLOCK_MONOTONIC_RAW_APPROX,
+            ClockSource::Monotonic | ClockSource::Boottime => CLOCK_MONOTONIC,
+            ClockSource::MonotonicRaw => CLOCK_MONOTONIC_RAW,
+        }
+    }
+
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+    #[inline]
+    fn clock_id(self) -> libc::clockid_t {
+        match self {
+            ClockSource::Coarse => libc::CLOCK_MONOTONIC_FAST,
+            _ => libc::CLOCK_MONOTONIC,
+        }
+    }
+}
 
 #[cfg(windows)]
 extern "system" {
     pub fn GetTickCount64() -> libc::c_ulonglong;
+    fn GetSystemTimeAdjustment(
+        time_adjustment: *mut u32,
+        time_increment: *mut u32,
+        time_adjustment_disabled: *mut i32,
+    ) -> i32;
 }
 
 #[cfg(any(target_os = "macos", target_os = "freebsd"))]
 #[allow(non_camel_case_types)]
-type clockid_t = libc::c_int;
+pub(crate) type clockid_t = libc::c_int;
+
+#[cfg(target_os = "macos")]
+const CLOCK_MONOTONIC_RAW: clockid_t = 4;
 
 #[cfg(target_os = "macos")]
 const CLOCK_MONOTONIC_RAW_APPROX: clockid_t = 5;
 
+#[cfg(target_os = "macos")]
+pub(crate) const CLOCK_MONOTONIC: clockid_t = 6;
+
 #[cfg(target_os = "macos")]
 extern "system" {
-    fn clock_gettime_nsec_np(clk_id: clockid_t) -> u64;
+    pub(crate) fn clock_gettime_nsec_np(clk_id: clockid_t) -> u64;
+    fn clock_getres(clk_id: clockid_t, res: *mut libc::timespec) -> libc::c_int;
 }
 
 #[cfg(target_os = "freebsd")]
@@ -57,154 +140,154 @@ mod js_imports {
     }
 }
 
-impl Instant {
-    /// Returns an instant corresponding to "now"
-    ///
-    /// This function also updates the stored instant.
-    pub fn now() -> Instant {
-        let now = Self::_now();
-        Self::_update(now);
-        Instant(now)
-    }
-
-    /// Returns an instant corresponding to "now" without updating the cached value.
-    /// After this, `recent()` will still return the old instant.
-    ///
-    /// `now()` is generally preferred over this function.
-    pub fn now_without_cache_update() -> Instant {
-        let now = Self::_now();
-        Instant(now)
-    }
+impl_cached_instant!(Instant, "an");
 
+impl Instant {
     /// Returns an instant corresponding to the latest update
+    ///
+    /// If self-refresh is enabled with `set_refresh_every()`, the stored
+    /// instant is also periodically updated by this function.
     pub fn recent() -> Instant {
+        // The stored instant is returned even after refreshing it, as another
+        // thread may have stored a more recent one
+        if refresh::INSTANT.is_due() {
+            Self::update();
+        }
         match Self::_recent() {
             0 => Instant::now(),
             recent => Instant(recent),
         }
     }
 
-    /// Update the stored instant
+    /// Returns the instant of the latest update, unless it is older than
+    /// `max_age`, in which case the stored instant is updated and returned.
     ///
-    /// This function should be called frequently, for example in an event loop
-    /// or using an `Updater` task.
-    pub fn update() {
+    /// This guards against a stalled or missing `Updater`, but it is not a
+    /// cheap read: the clock is read on every call to check the age of the
+    /// stored instant, which costs as much as `now_without_cache_update()`.
+    /// Unlike `now()`, the shared value is only written to when it is too
+    /// old, and callers get the same instant as `recent()` otherwise.
+    ///
+    /// Where the cost of reading the clock matters, prefer `recent()` along
+    /// with periodic `cache_age()` checks, or `set_refresh_every()`.
+    pub fn recent_within(max_age: Duration) -> Instant {
+        let recent = GLOBAL.instant.load(Ordering::Relaxed);
         let now = Self::_now();
+        if recent != 0 && Instant(now) - Instant(recent) <= max_age {
+            return Instant(recent);
+        }
         Self::_update(now);
+        Instant(now)
     }
 
-    /// Returns the amount of time elapsed from another instant to this one
-    #[inline]
-    pub fn duration_since(&self, earlier: Instant) -> Duration {
-        *self - earlier
-    }
-
-    /// Returns the amount of time elapsed between the this instant was created
-    /// and the latest update
-    #[inline]
-    pub fn elapsed_since_recent(&self) -> Duration {
-        Self::recent() - *self
+    /// Returns the time elapsed since the latest update of the stored
+    /// instant, or `None` if it was never updated.
+    ///
+    /// This reads the clock, like `now_without_cache_update()`. It is meant
+    /// for health checks detecting a stalled `Updater`, not for hot paths.
+    pub fn cache_age() -> Option<Duration> {
+        match GLOBAL.instant.load(Ordering::Relaxed) {
+            0 => None,
+            recent => Some(Instant(Self::_now()) - Instant(recent)),
+        }
     }
 
-    /// Returns the amount of time elapsed since this instant was created
+    /// Makes `recent()` update the stored instant once every `calls` calls
+    /// on each thread, so that it stays approximately fresh without an
+    /// `Updater`.
     ///
-    /// This function also updates the stored instant.
-    #[inline]
-    pub fn elapsed(&self) -> Duration {
-        Self::now() - *self
+    /// This is a process-wide setting. `0` disables self-refresh, which is
+    /// the default.
+    pub fn set_refresh_every(calls: u32) {
+        refresh::INSTANT.set(calls);
     }
 
-    /// Return a representation of this instant as a number of "ticks".
-    ///
-    /// Note that length of a 'tick' is not guaranteed to represent
-    /// the same amount of time across different platforms, or from
-    /// one version of `coarsetime` to another.
-    ///
-    /// Note also that the instant represented by "0" ticks is
-    /// unspecified.  It is not guaranteed to be the same time across
-    /// different platforms, or from one version of `coarsetime` to
-    /// another.
-    ///
-    /// This API is mainly intended for applications that need to
-    /// store the value of an `Instant` in an
-    /// [`AtomicU64`](std::sync::atomic::AtomicU64).
-    #[inline]
-    pub const fn as_ticks(&self) -> u64 {
-        self.as_u64()
+    /// Returns the number of calls to `recent()` after which the stored
+    /// instant is updated, or `0` if self-refresh is disabled
+    pub fn refresh_every() -> u32 {
+        refresh::INSTANT.get()
     }
 
-    /// Create an `Instant` from a number of "ticks".
+    /// Selects the operating system clock used to compute instants.
     ///
-    /// Note that length of a 'tick' is not guaranteed to represent
-    /// the same amount of time across different platforms, or from
-    /// one version of `coarsetime` to another.
+    /// This is a process-wide setting, that also applies to the `Updater`.
+    /// Instants computed using different clock sources may not be comparable,
+    /// so this function should be called once, before any instant is taken.
     ///
-    /// Note also that the instant represented by "0" ticks is
-    /// unspecified.  It is not guaranteed to be the same time across
-    /// different platforms, or from one version of `coarsetime` to
-    /// another.
-    #[inline]
-    pub const fn from_ticks(ticks: u64) -> Instant {
-        Self::from_u64(ticks)
-    }
-
-    #[doc(hidden)]
-    #[inline]
-    pub const fn as_u64(&self) -> u64 {
-        self.0
+    /// The cached instant is immediately refreshed using the new clock source.
+    pub fn set_clock_source(source: ClockSource) {
+        CLOCK_SOURCE.store(source as u8, Ordering::Relaxed);
+        // The new clock may be behind the previous one
+        GLOBAL.reset();
     }
 
-    #[doc(hidden)]
+    /// Returns the operating system clock currently used to compute instants
     #[inline]
-    pub const fn from_u64(ts: u64) -> Instant {
-        Instant(ts)
+    pub fn clock_source() -> ClockSource {
+        ClockSource::current()
     }
 
-    /// Calculate an `Instant` that is a `Duration` later, saturating on overflow
-    #[inline]
-    pub const fn saturating_add(self, rhs: Duration) -> Instant {
-        Instant(self.0.saturating_add(rhs.as_u64()))
+    /// Returns the granularity of the clock currently used to compute
+    /// instants.
+    ///
+    /// With the default clock source, this is typically a few milliseconds,
+    /// depending on the kernel configuration. Updates more frequent than that
+    /// are pointless.
+    pub fn resolution() -> Duration {
+        Duration::from_u64(Self::_resolution().max(1))
     }
 
-    /// Calculate an `Instant` that is a `Duration` later, returning `None` on overflow
+    /// Returns the amount of time elapsed from another instant to this one,
+    /// which is negative if that instant is later than this one
     #[inline]
-    pub fn checked_add(self, rhs: Duration) -> Option<Instant> {
-        self.0.checked_add(rhs.as_u64()).map(Instant)
+    pub fn signed_duration_since(&self, earlier: Instant) -> SignedDuration {
+        SignedDuration::between(self.0, earlier.0)
     }
 
-    /// Calculate an `Instant` that is a `Duration` earlier, saturating on underflow
+    /// Rounds the instant down to a multiple of `period`.
+    ///
+    /// Multiples are counted from the unspecified origin of the clock, so
+    /// they are only meaningful within a process, for example to assign
+    /// instants to time buckets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
     #[inline]
-    pub const fn saturating_sub(self, rhs: Duration) -> Instant {
-        Instant(self.0.saturating_sub(rhs.as_u64()))
+    pub fn align_down(&self, period: Duration) -> Instant {
+        Instant(self.0 - _rem_ticks(self.0, period.as_u64()))
     }
 
-    /// Calculate an `Instant` that is a `Duration` earlier, returning `None` on underflow
     #[inline]
-    pub fn checked_sub(self, rhs: Duration) -> Option<Instant> {
-        self.0.checked_sub(rhs.as_u64()).map(Instant)
+    pub(crate) fn _now() -> u64 {
+        #[cfg(feature = "mock")]
+        if let Some(now) = super::mock::instant() {
+            return now;
+        }
+        Self::_os_now()
     }
 
     #[cfg(any(target_os = "linux", target_os = "android"))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let mut tp = MaybeUninit::<libc::timespec>::uninit();
         let tp = unsafe {
-            libc::clock_gettime(libc::CLOCK_MONOTONIC_COARSE, tp.as_mut_ptr());
+            libc::clock_gettime(ClockSource::current().clock_id(), tp.as_mut_ptr());
             tp.assume_init()
         };
         _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
     }
 
     #[cfg(target_os = "macos")]
-    fn _now() -> u64 {
-        let nsec = unsafe { clock_gettime_nsec_np(CLOCK_MONOTONIC_RAW_APPROX) };
+    pub(crate) fn _os_now() -> u64 {
+        let nsec = unsafe { clock_gettime_nsec_np(ClockSource::current().clock_id()) };
         _nsecs_to_u64(nsec)
     }
 
     #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let mut tp = MaybeUninit::<libc::timespec>::uninit();
         let tp = unsafe {
-            libc::clock_gettime(libc::CLOCK_MONOTONIC_FAST, tp.as_mut_ptr());
+            libc::clock_gettime(ClockSource::current().clock_id(), tp.as_mut_ptr());
             tp.assume_init()
         };
         _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
@@ -220,7 +303,7 @@ impl Instant {
             target_os = "dragonfly"
         ))
     ))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let mut tv = MaybeUninit::<libc::timeval>::uninit();
         let tv = unsafe {
             libc::gettimeofday(tv.as_mut_ptr(), null_mut());
@@ -230,13 +313,13 @@ impl Instant {
     }
 
     #[cfg(windows)]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let tc = unsafe { GetTickCount64() } as u64;
         _millis_to_u64(tc)
     }
 
     #[cfg(all(target_os = "wasi", not(feature = "wasi-abi2")))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         use wasix::{clock_time_get, CLOCKID_MONOTONIC, CLOCKID_REALTIME};
         let nsec = unsafe { clock_time_get(CLOCKID_MONOTONIC, 1_000_000) }
             .or_else(|_| unsafe { clock_time_get(CLOCKID_REALTIME, 1_000_000) })
@@ -245,7 +328,7 @@ impl Instant {
     }
 
     #[cfg(all(target_os = "wasi", feature = "wasi-abi2"))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let nsec = wasi_abi2::clocks::monotonic_clock::now();
         _nsecs_to_u64(nsec)
     }
@@ -254,79 +337,114 @@ impl Instant {
         any(target_arch = "wasm32", target_arch = "wasm64"),
         target_os = "unknown"
     ))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         _millis_to_u64(js_imports::performance::now() as u64)
     }
 
     #[cfg(all(target_arch = "x86_64", target_env = "sgx", target_vendor = "fortanix"))]
-    fn _now() -> u64 {
+    pub(crate) fn _os_now() -> u64 {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap();
         timestamp.as_secs() * 1_000_000_000 + (timestamp.subsec_nanos() as u64)
     }
 
-    #[inline]
-    fn _update(now: u64) {
-        RECENT.store(now, Ordering::Relaxed)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn _resolution() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_getres(ClockSource::current().clock_id(), tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
     }
 
-    #[inline]
-    fn _recent() -> u64 {
-        let recent = RECENT.load(Ordering::Relaxed);
-        if recent != 0 {
-            recent
-        } else {
-            let now = Self::_now();
-            Self::_update(now);
-            Self::_recent()
-        }
+    #[cfg(target_os = "macos")]
+    fn _resolution() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            clock_getres(ClockSource::current().clock_id(), tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
     }
-}
 
-impl Default for Instant {
-    fn default() -> Instant {
-        Self::now()
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+    fn _resolution() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_getres(ClockSource::current().clock_id(), tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
     }
-}
 
-impl Sub<Instant> for Instant {
-    type Output = Duration;
+    #[cfg(all(
+        unix,
+        not(any(
+            target_os = "macos",
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly"
+        ))
+    ))]
+    fn _resolution() -> u64 {
+        _timeval_to_u64(0, 1)
+    }
 
-    #[inline]
-    fn sub(self, other: Instant) -> Duration {
-        Duration::from_u64(self.0.saturating_sub(other.0))
+    #[cfg(windows)]
+    fn _resolution() -> u64 {
+        let (mut adjustment, mut increment, mut disabled) = (0, 0, 0);
+        if unsafe { GetSystemTimeAdjustment(&mut adjustment, &mut increment, &mut disabled) } == 0 {
+            // Default timer interrupt period
+            return _nsecs_to_u64(15_625_000);
+        }
+        _nsecs_to_u64(increment as u64 * 100)
     }
-}
 
-impl Sub<Duration> for Instant {
-    type Output = Instant;
+    #[cfg(all(target_os = "wasi", not(feature = "wasi-abi2")))]
+    fn _resolution() -> u64 {
+        use wasix::{clock_res_get, CLOCKID_MONOTONIC, CLOCKID_REALTIME};
+        let nsec = unsafe { clock_res_get(CLOCKID_MONOTONIC) }
+            .or_else(|_| unsafe { clock_res_get(CLOCKID_REALTIME) })
+            .expect("Clock not available");
+        _nsecs_to_u64(nsec)
+    }
 
-    #[inline]
-    fn sub(self, rhs: Duration) -> Instant {
-        Instant(self.0 - rhs.as_u64())
+    #[cfg(all(target_os = "wasi", feature = "wasi-abi2"))]
+    fn _resolution() -> u64 {
+        let nsec = wasi_abi2::clocks::monotonic_clock::resolution();
+        _nsecs_to_u64(nsec)
     }
-}
 
-impl SubAssign<Duration> for Instant {
-    #[inline]
-    fn sub_assign(&mut self, rhs: Duration) {
-        *self = *self - rhs;
+    #[cfg(all(
+        any(target_arch = "wasm32", target_arch = "wasm64"),
+        target_os = "unknown"
+    ))]
+    fn _resolution() -> u64 {
+        _millis_to_u64(1)
     }
-}
 
-impl Add<Duration> for Instant {
-    type Output = Instant;
+    #[cfg(all(target_arch = "x86_64", target_env = "sgx", target_vendor = "fortanix"))]
+    fn _resolution() -> u64 {
+        1
+    }
 
     #[inline]
-    fn add(self, rhs: Duration) -> Instant {
-        Instant(self.0 + rhs.as_u64())
+    fn _update(now: u64) {
+        GLOBAL.advance_instant(now)
     }
-}
 
-impl AddAssign<Duration> for Instant {
     #[inline]
-    fn add_assign(&mut self, rhs: Duration) {
-        *self = *self + rhs;
+    fn _recent() -> u64 {
+        let recent = GLOBAL.instant.load(Ordering::Relaxed);
+        if recent != 0 {
+            recent
+        } else {
+            let now = Self::_now();
+            Self::_update(now);
+            Self::_recent()
+        }
     }
 }
@@ -4,8 +4,10 @@
 //! from the standard library, with the following differences:
 //!
 //! * Speed is privileged over accuracy. In particular, `CLOCK_MONOTONIC_COARSE`
-//!   is used to retrieve the clock value on Linux systems, and transformations avoid
-//!   operations that can be slow on non-Intel systems.
+//!   and `CLOCK_REALTIME_COARSE` are used to retrieve the clock values on Linux
+//!   systems, and transformations avoid operations that can be slow on
+//!   non-Intel systems.
+//!   A more precise clock can be selected with `Instant::set_clock_source()`.
 //! * The number of system calls can be kept to a minimum. The "most recent
 //!   timestamp" is always kept in memory.
 //!   It can be read with just a load operation, and can be
@@ -20,18 +22,50 @@
 
 #![allow(clippy::trivially_copy_pass_by_ref)]
 
+#[cfg(all(
+    any(feature = "tokio", feature = "smol"),
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+mod async_updater;
+mod boot_instant;
 mod clock;
+mod coarse_clock;
 mod duration;
 mod helpers;
+mod http_date;
 mod instant;
+#[cfg(feature = "mock")]
+mod mock;
+mod refresh;
+mod rfc3339;
+mod seqlock;
+#[cfg(feature = "serde")]
+pub mod serde;
+mod signed_duration;
+#[cfg(feature = "tsc")]
+mod tsc;
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 mod updater;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(all(
+    any(feature = "tokio", feature = "smol"),
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+pub use self::async_updater::*;
+pub use self::boot_instant::*;
 pub use self::clock::*;
+pub use self::coarse_clock::*;
 pub use self::duration::*;
+pub use self::http_date::*;
 pub use self::instant::*;
+#[cfg(feature = "mock")]
+pub use self::mock::*;
+pub use self::rfc3339::*;
+pub use self::signed_duration::*;
+#[cfg(feature = "tsc")]
+pub use self::tsc::*;
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 pub use self::updater::*;
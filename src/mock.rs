@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::clock::*;
+use super::coarse_clock::*;
+use super::duration::*;
+use super::instant::*;
+
+static FROZEN: AtomicBool = AtomicBool::new(false);
+static INSTANT: AtomicU64 = AtomicU64::new(0);
+static UNIX_TS: AtomicU64 = AtomicU64::new(0);
+
+/// A controllable clock, for testing purposes.
+///
+/// Once frozen, `Instant::now()`, `Instant::recent()`,
+/// `Clock::now_since_epoch()` and `Clock::recent_since_epoch()` only change
+/// when the mock clock is explicitly adjusted, so that time-dependent code can
+/// be tested instantly and reproducibly.
+///
+/// The mock clock is process-wide. It is only available when the `mock`
+/// feature is enabled, and should not be used in production code.
+#[derive(Debug)]
+pub struct MockClock;
+
+impl MockClock {
+    /// Freezes the clocks at their current values
+    pub fn freeze() {
+        if !Self::is_frozen() {
+            Self::set(
+                Instant::now_without_cache_update(),
+                Clock::now_since_epoch(),
+            );
+        }
+    }
+
+    /// Resumes the clocks, that now return the actual time again
+    pub fn unfreeze() {
+        FROZEN.store(false, Ordering::Relaxed);
+        CoarseClock::global().reset();
+    }
+
+    /// Returns `true` if the clocks are currently frozen
+    #[inline]
+    pub fn is_frozen() -> bool {
+        FROZEN.load(Ordering::Relaxed)
+    }
+
+    /// Freezes the clocks at the specified instant and system time
+    pub fn set(instant: Instant, since_epoch: UnixTimeStamp) {
+        INSTANT.store(instant.as_u64(), Ordering::Relaxed);
+        UNIX_TS.store(since_epoch.as_u64(), Ordering::Relaxed);
+        FROZEN.store(true, Ordering::Relaxed);
+        CoarseClock::global().reset();
+    }
+
+    /// Freezes the clocks, and sets the monotonic clock to the specified instant
+    pub fn set_instant(instant: Instant) {
+        Self::freeze();
+        Self::set(instant, Duration::from_u64(UNIX_TS.load(Ordering::Relaxed)));
+    }
+
+    /// Freezes the clocks, and sets the system time to the specified timestamp
+    pub fn set_since_epoch(since_epoch: UnixTimeStamp) {
+        Self::freeze();
+        Self::set(
+            Instant::from_u64(INSTANT.load(Ordering::Relaxed)),
+            since_epoch,
+        );
+    }
+
+    /// Freezes the clocks, and moves both of them forward by the specified duration
+    pub fn advance(duration: Duration) {
+        Self::freeze();
+        let instant = Instant::from_u64(INSTANT.load(Ordering::Relaxed)) + duration;
+        let since_epoch = Duration::from_u64(UNIX_TS.load(Ordering::Relaxed)) + duration;
+        Self::set(instant, since_epoch);
+    }
+}
+
+#[inline]
+pub(crate) fn instant() -> Option<u64> {
+    if MockClock::is_frozen() {
+        Some(INSTANT.load(Ordering::Relaxed))
+    } else {
+        None
+    }
+}
+
+#[inline]
+pub(crate) fn unix_ts() -> Option<u64> {
+    if MockClock::is_frozen() {
+        Some(UNIX_TS.load(Ordering::Relaxed))
+    } else {
+        None
+    }
+}
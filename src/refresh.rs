@@ -0,0 +1,52 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread::LocalKey;
+
+thread_local! {
+    static INSTANT_CALLS: Cell<u32> = const { Cell::new(0) };
+    static CLOCK_CALLS: Cell<u32> = const { Cell::new(0) };
+}
+
+// Self-refresh settings of `Instant::recent()` and `Clock::recent()`
+pub(crate) static INSTANT: Refresh = Refresh::new(&INSTANT_CALLS);
+pub(crate) static CLOCK: Refresh = Refresh::new(&CLOCK_CALLS);
+
+/// Counts the reads of a cached value on each thread, so that the cache can
+/// be refreshed every `every` reads, without a background thread.
+pub(crate) struct Refresh {
+    every: AtomicU32,
+    calls: &'static LocalKey<Cell<u32>>,
+}
+
+impl Refresh {
+    const fn new(calls: &'static LocalKey<Cell<u32>>) -> Refresh {
+        Refresh {
+            every: AtomicU32::new(0),
+            calls,
+        }
+    }
+
+    pub(crate) fn set(&self, every: u32) {
+        self.every.store(every, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> u32 {
+        self.every.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the cached value should be refreshed by the current
+    /// read
+    #[inline]
+    pub(crate) fn is_due(&self) -> bool {
+        let every = self.every.load(Ordering::Relaxed);
+        every != 0
+            && self
+                .calls
+                .try_with(|calls| {
+                    let n = calls.get() + 1;
+                    calls.set(if n >= every { 0 } else { n });
+                    n >= every
+                })
+                .unwrap_or(false)
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use super::clock::*;
+use super::duration::*;
+use super::helpers::*;
+
+const RFC3339_MAX_LEN: usize = 27;
+
+/// The precision of the fractional seconds in RFC 3339 timestamps
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub enum SecondsFormat {
+    /// Whole seconds: `2024-01-02T03:04:05Z`
+    #[default]
+    Secs,
+    /// Milliseconds: `2024-01-02T03:04:05.678Z`
+    Millis,
+    /// Microseconds: `2024-01-02T03:04:05.678901Z`
+    Micros,
+}
+
+/// An RFC 3339 representation of a date, in UTC.
+///
+/// This is stored inline, so that formatting a date doesn't require any
+/// allocations.
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct Rfc3339 {
+    buf: [u8; RFC3339_MAX_LEN],
+    len: u8,
+}
+
+impl Rfc3339 {
+    /// Returns the timestamp as a string slice
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // The buffer only contains ASCII characters
+        std::str::from_utf8(&self.buf[..self.len as usize]).unwrap()
+    }
+}
+
+impl Deref for Rfc3339 {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Rfc3339 {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl fmt::Debug for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match f.precision() {
+            None | Some(0) => SecondsFormat::Secs,
+            Some(1..=3) => SecondsFormat::Millis,
+            Some(_) => SecondsFormat::Micros,
+        };
+        _pad(f, &self.to_rfc3339(format))
+    }
+}
+
+impl fmt::Debug for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Date")
+            .field(&format_args!("{}", self.to_rfc3339(SecondsFormat::Micros)))
+            .finish()
+    }
+}
+
+/// Writes `value` as a zero-padded decimal number filling `buf`
+#[inline]
+pub(crate) fn put_digits(buf: &mut [u8], mut value: u64) {
+    for c in buf.iter_mut().rev() {
+        *c = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+}
+
+/// Parses a fixed-length decimal number
+#[inline]
+pub(crate) fn get_digits(buf: &[u8]) -> Option<u32> {
+    buf.iter().try_fold(0u32, |value, &c| {
+        if c.is_ascii_digit() {
+            Some(value * 10 + (c - b'0') as u32)
+        } else {
+            None
+        }
+    })
+}
+
+impl Date {
+    /// Formats the date as an RFC 3339 timestamp in UTC, such as
+    /// `2024-01-02T03:04:05.678Z`.
+    ///
+    /// Fractional seconds are truncated to the requested precision.
+    pub fn to_rfc3339(&self, format: SecondsFormat) -> Rfc3339 {
+        let since_epoch = self.as_unix_timestamp();
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = _days_to_civil(secs / 86_400);
+        let secs_of_day = secs % 86_400;
+        let mut buf = *b"0000-00-00T00:00:00.000000Z";
+        put_digits(&mut buf[0..4], year);
+        put_digits(&mut buf[5..7], month as u64);
+        put_digits(&mut buf[8..10], day as u64);
+        put_digits(&mut buf[11..13], secs_of_day / 3_600);
+        put_digits(&mut buf[14..16], secs_of_day / 60 % 60);
+        put_digits(&mut buf[17..19], secs_of_day % 60);
+        let nanos = since_epoch.subsec_nanos() as u64;
+        let len = match format {
+            SecondsFormat::Secs => 19,
+            SecondsFormat::Millis => {
+                put_digits(&mut buf[20..23], nanos / 1_000_000);
+                23
+            }
+            SecondsFormat::Micros => {
+                put_digits(&mut buf[20..26], nanos / 1_000);
+                26
+            }
+        };
+        buf[len] = b'Z';
+        Rfc3339 {
+            buf,
+            len: len as u8 + 1,
+        }
+    }
+
+    /// Parses an RFC 3339 timestamp, such as `2024-01-02T03:04:05.678Z` or
+    /// `2024-01-02T05:04:05+02:00`.
+    ///
+    /// Parsing is strict: the date and time separator must be `T`, and the
+    /// time zone offset is mandatory. Leap seconds, dates before the UNIX
+    /// epoch, and digits beyond nanosecond precision are not supported.
+    pub fn parse_rfc3339(s: &str) -> Result<Date, ParseDateError> {
+        let err = ParseDateError(());
+        let s = s.as_bytes();
+        if s.len() < 20
+            || s[4] != b'-'
+            || s[7] != b'-'
+            || !matches!(s[10], b'T' | b't')
+            || s[13] != b':'
+            || s[16] != b':'
+        {
+            return Err(err);
+        }
+        let year = get_digits(&s[0..4]).ok_or(err)?;
+        let month = get_digits(&s[5..7]).ok_or(err)?;
+        let day = get_digits(&s[8..10]).ok_or(err)?;
+        let hour = get_digits(&s[11..13]).ok_or(err)?;
+        let min = get_digits(&s[14..16]).ok_or(err)?;
+        let sec = get_digits(&s[17..19]).ok_or(err)?;
+        if !(1..=12).contains(&month)
+            || day < 1
+            || day > _days_in_month(year, month)
+            || hour > 23
+            || min > 59
+            || sec > 59
+        {
+            return Err(err);
+        }
+
+        let mut rest = &s[19..];
+        let mut nanos = 0u32;
+        if let Some((b'.', frac)) = rest.split_first() {
+            let digits = frac.iter().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 || digits > 9 {
+                return Err(err);
+            }
+            nanos = get_digits(&frac[..digits]).ok_or(err)? * 10u32.pow(9 - digits as u32);
+            rest = &frac[digits..];
+        }
+
+        let offset = match rest {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), hh1, hh2, b':', mm1, mm2] => {
+                let offset_hours = get_digits(&[*hh1, *hh2]).ok_or(err)?;
+                let offset_mins = get_digits(&[*mm1, *mm2]).ok_or(err)?;
+                if offset_hours > 23 || offset_mins > 59 {
+                    return Err(err);
+                }
+                let offset = (offset_hours * 3_600 + offset_mins * 60) as i64;
+                if *sign == b'-' {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+            _ => return Err(err),
+        };
+
+        let days = _civil_to_days(year, month, day);
+        let secs = days * 86_400 + (hour * 3_600 + min * 60 + sec) as i64 - offset;
+        if !(0..1 << 32).contains(&secs) {
+            return Err(err);
+        }
+        Ok(Date::from_unix_timestamp(Duration::new(secs as u64, nanos)))
+    }
+}
+
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    /// Parses an RFC 3339 timestamp
+    #[inline]
+    fn from_str(s: &str) -> Result<Date, ParseDateError> {
+        Date::parse_rfc3339(s)
+    }
+}
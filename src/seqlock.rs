@@ -0,0 +1,91 @@
+use std::fmt;
+use std::hint::spin_loop;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+/// A sequence lock, to publish a small set of words that readers always
+/// observe together, without ever blocking them.
+pub(crate) struct SeqLock<const N: usize> {
+    seq: AtomicU64,
+    words: [AtomicU64; N],
+}
+
+impl<const N: usize> SeqLock<N> {
+    // Atomics are not `Copy`, but arrays can be built from a constant
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+
+    pub(crate) const fn new() -> Self {
+        SeqLock {
+            seq: AtomicU64::new(0),
+            words: [Self::ZERO; N],
+        }
+    }
+
+    /// Returns a consistent copy of the latest published values
+    #[inline]
+    pub(crate) fn read(&self) -> [u64; N] {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 != 0 {
+                spin_loop();
+                continue;
+            }
+            let mut values = [0u64; N];
+            for (value, word) in values.iter_mut().zip(&self.words) {
+                *value = word.load(Ordering::Relaxed);
+            }
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return values;
+            }
+        }
+    }
+
+    /// Publishes new values.
+    ///
+    /// Returns `false` without publishing anything if another writer is
+    /// currently publishing.
+    pub(crate) fn try_write(&self, values: [u64; N]) -> bool {
+        self.try_write_if(values, |_| true)
+    }
+
+    /// Publishes new values, if `cond` returns `true` for the currently
+    /// published ones.
+    ///
+    /// Returns `false` without publishing anything if the condition is not
+    /// met, or if another writer is currently publishing.
+    pub(crate) fn try_write_if<F>(&self, values: [u64; N], cond: F) -> bool
+    where
+        F: FnOnce(&[u64; N]) -> bool,
+    {
+        let seq = self.seq.load(Ordering::Relaxed);
+        if seq & 1 != 0
+            || self
+                .seq
+                .compare_exchange(seq, seq + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        fence(Ordering::Release);
+        let mut current = [0u64; N];
+        for (value, word) in current.iter_mut().zip(&self.words) {
+            *value = word.load(Ordering::Relaxed);
+        }
+        if !cond(&current) {
+            self.seq.store(seq + 2, Ordering::Release);
+            return false;
+        }
+        for (value, word) in values.iter().zip(&self.words) {
+            word.store(*value, Ordering::Relaxed);
+        }
+        self.seq.store(seq + 2, Ordering::Release);
+        true
+    }
+}
+
+impl<const N: usize> fmt::Debug for SeqLock<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SeqLock").field(&self.read()).finish()
+    }
+}
@@ -0,0 +1,257 @@
+//! `serde` support.
+//!
+//! By default, `Duration` (and thus `UnixTimeStamp`) and `Date` are
+//! serialized as a `{ secs, nanos }` structure, like `std::time::Duration`.
+//!
+//! The modules below can be used with `#[serde(with = "...")]` to select a
+//! different representation:
+//!
+//! * `coarsetime::serde::secs_f64`: a floating point number of seconds
+//! * `coarsetime::serde::millis`: an integer number of milliseconds
+//! * `coarsetime::serde::secs_nanos`: a `{ secs, nanos }` structure
+//! * `coarsetime::serde::human`: a human-readable string, such as `"2.5s"`
+//! * `coarsetime::serde::rfc3339`: an RFC 3339 string, for `Date` only
+
+use std::fmt;
+
+use ::serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use ::serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use super::clock::*;
+use super::duration::*;
+
+const FIELDS: &[&str] = &["secs", "nanos"];
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        secs_nanos::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        secs_nanos::deserialize(deserializer)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        secs_nanos::serialize(&self.as_unix_timestamp(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        secs_nanos::deserialize(deserializer).map(Date::from_unix_timestamp)
+    }
+}
+
+/// Serializes a `Duration` as a floating point number of seconds
+pub mod secs_f64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        if !(0.0..(1u64 << 32) as f64).contains(&secs) {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Float(secs),
+                &"a non-negative number of seconds",
+            ));
+        }
+        Ok(Duration::from_u64(
+            (secs * (1u64 << 32) as f64).round() as u64
+        ))
+    }
+}
+
+/// Serializes a `Duration` as an integer number of milliseconds
+pub mod millis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = u64::deserialize(deserializer)?;
+        if millis >= (1u64 << 32) * 1_000 {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(millis),
+                &"a number of milliseconds below 2^32 seconds",
+            ));
+        }
+        Ok(Duration::new(
+            millis / 1_000,
+            (millis % 1_000) as u32 * 1_000_000,
+        ))
+    }
+}
+
+/// Serializes a `Duration` as a `{ secs, nanos }` structure
+pub mod secs_nanos {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Duration", 2)?;
+        state.serialize_field("secs", &duration.as_secs())?;
+        state.serialize_field("nanos", &duration.subsec_nanos())?;
+        state.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserializer.deserialize_struct("Duration", FIELDS, SecsNanosVisitor)
+    }
+}
+
+/// Serializes a `Duration` as a human-readable string, such as `"2.5s"`.
+///
+/// Durations are always serialized as a number of seconds, such as `"90s"`.
+/// Any string accepted by `Duration::from_str()`, such as `"1m 30s"`, can be
+/// deserialized.
+pub mod human {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        let nanos = duration.subsec_nanos();
+        if nanos == 0 {
+            return serializer.collect_str(&format_args!("{}s", duration.as_secs()));
+        }
+        let (mut frac, mut digits) = (nanos, 9);
+        while frac % 10 == 0 {
+            frac /= 10;
+            digits -= 1;
+        }
+        serializer.collect_str(&format_args!(
+            "{}.{:0digits$}s",
+            duration.as_secs(),
+            frac,
+            digits = digits
+        ))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserializer.deserialize_str(HumanVisitor)
+    }
+}
+
+/// Serializes a `Date` as an RFC 3339 string, with microsecond precision
+pub mod rfc3339 {
+    use super::*;
+    use crate::SecondsFormat;
+
+    pub fn serialize<S: Serializer>(date: &Date, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.to_rfc3339(SecondsFormat::Micros))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        deserializer.deserialize_str(Rfc3339Visitor)
+    }
+}
+
+enum Field {
+    Secs,
+    Nanos,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Field, D::Error> {
+        struct FieldVisitor;
+
+        impl Visitor<'_> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("`secs` or `nanos`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Field, E> {
+                match value {
+                    "secs" => Ok(Field::Secs),
+                    "nanos" => Ok(Field::Nanos),
+                    _ => Err(de::Error::unknown_field(value, FIELDS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+struct SecsNanosVisitor;
+
+impl SecsNanosVisitor {
+    fn duration<E: de::Error>(secs: u64, nanos: u32) -> Result<Duration, E> {
+        if secs >= 1 << 32 || nanos >= 1_000_000_000 {
+            return Err(de::Error::custom("duration out of range"));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl<'de> Visitor<'de> for SecsNanosVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("struct Duration")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Duration, A::Error> {
+        let secs = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let nanos = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Self::duration(secs, nanos)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Duration, A::Error> {
+        let (mut secs, mut nanos) = (None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::Secs if secs.is_some() => return Err(de::Error::duplicate_field("secs")),
+                Field::Secs => secs = Some(map.next_value()?),
+                Field::Nanos if nanos.is_some() => return Err(de::Error::duplicate_field("nanos")),
+                Field::Nanos => nanos = Some(map.next_value()?),
+            }
+        }
+        let secs = secs.ok_or_else(|| de::Error::missing_field("secs"))?;
+        let nanos = nanos.ok_or_else(|| de::Error::missing_field("nanos"))?;
+        Self::duration(secs, nanos)
+    }
+}
+
+struct HumanVisitor;
+
+impl Visitor<'_> for HumanVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a duration such as \"2.5s\" or \"250ms\"")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Duration, E> {
+        value
+            .parse()
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+struct Rfc3339Visitor;
+
+impl Visitor<'_> for Rfc3339Visitor {
+    type Value = Date;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an RFC 3339 timestamp")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Date, E> {
+        Date::parse_rfc3339(value)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
@@ -0,0 +1,267 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::*;
+
+use super::duration::*;
+
+/// A span of time that can be negative, such as the difference between two
+/// instants in an unknown order, or a clock offset.
+///
+/// It uses the same fixed-point representation as `Duration`, with a sign.
+/// Converting a negative `SignedDuration` into a `Duration` fails.
+///
+/// Like `abs()`, negation saturates: the opposite of the most negative
+/// duration is the maximum positive one.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq, Default)]
+pub struct SignedDuration(i64);
+
+/// An error returned when a duration doesn't fit in the target type, either
+/// because it is negative or because it is too large
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TryFromDurationError(());
+
+impl fmt::Display for TryFromDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("duration out of range")
+    }
+}
+
+impl Error for TryFromDurationError {}
+
+impl SignedDuration {
+    /// A duration of zero time
+    pub const ZERO: SignedDuration = SignedDuration(0);
+
+    /// Creates a new `SignedDuration` from the specified number of seconds
+    #[inline]
+    pub const fn from_secs(secs: i64) -> SignedDuration {
+        SignedDuration(secs.saturating_mul(1 << 32))
+    }
+
+    /// Returns the number of whole seconds, rounded towards zero
+    #[inline]
+    pub const fn as_secs(&self) -> i64 {
+        self.signum() * self.unsigned_abs().as_secs() as i64
+    }
+
+    /// Returns the fractional part in nanoseconds, with the same sign as the
+    /// duration
+    #[inline]
+    pub const fn subsec_nanos(&self) -> i32 {
+        self.signum() as i32 * self.unsigned_abs().subsec_nanos() as i32
+    }
+
+    /// Returns the duration as a floating point number of seconds
+    #[inline]
+    pub fn as_f64(&self) -> f64 {
+        self.0 as f64 / (1u64 << 32) as f64
+    }
+
+    /// Returns the absolute value of the duration, saturating at the maximum
+    /// positive value
+    #[inline]
+    pub const fn abs(self) -> SignedDuration {
+        SignedDuration(self.0.saturating_abs())
+    }
+
+    /// Returns the absolute value of the duration, as a `Duration`
+    #[inline]
+    pub const fn unsigned_abs(self) -> Duration {
+        Duration::from_u64(self.0.unsigned_abs())
+    }
+
+    /// Returns `-1`, `0` or `1` depending on the sign of the duration
+    #[inline]
+    pub const fn signum(&self) -> i64 {
+        self.0.signum()
+    }
+
+    /// Returns `true` if the duration is strictly negative
+    #[inline]
+    pub const fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    /// Returns `true` if the duration is strictly positive
+    #[inline]
+    pub const fn is_positive(&self) -> bool {
+        self.0 > 0
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub const fn as_i64(&self) -> i64 {
+        self.0
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub const fn from_i64(ts: i64) -> SignedDuration {
+        SignedDuration(ts)
+    }
+
+    /// Computes `a - b` for two points of the same timeline, saturating
+    #[inline]
+    pub(crate) fn between(a: u64, b: u64) -> SignedDuration {
+        let diff = a as i128 - b as i128;
+        SignedDuration(diff.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    /// Add two durations, saturating on overflow
+    #[inline]
+    pub const fn saturating_add(self, rhs: SignedDuration) -> SignedDuration {
+        SignedDuration(self.0.saturating_add(rhs.0))
+    }
+
+    /// Add two durations, returning `None` on overflow
+    #[inline]
+    pub fn checked_add(self, rhs: SignedDuration) -> Option<SignedDuration> {
+        self.0.checked_add(rhs.0).map(SignedDuration)
+    }
+
+    /// Subtract two durations, saturating on overflow
+    #[inline]
+    pub const fn saturating_sub(self, rhs: SignedDuration) -> SignedDuration {
+        SignedDuration(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Subtract two durations, returning `None` on overflow
+    #[inline]
+    pub fn checked_sub(self, rhs: SignedDuration) -> Option<SignedDuration> {
+        self.0.checked_sub(rhs.0).map(SignedDuration)
+    }
+}
+
+impl Neg for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn neg(self) -> SignedDuration {
+        SignedDuration(self.0.checked_neg().unwrap_or(i64::MAX))
+    }
+}
+
+impl Add for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn add(self, rhs: SignedDuration) -> SignedDuration {
+        SignedDuration(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for SignedDuration {
+    #[inline]
+    fn add_assign(&mut self, rhs: SignedDuration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn sub(self, rhs: SignedDuration) -> SignedDuration {
+        SignedDuration(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for SignedDuration {
+    #[inline]
+    fn sub_assign(&mut self, rhs: SignedDuration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Add<Duration> for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn add(self, rhs: Duration) -> SignedDuration {
+        SignedDuration(
+            self.0
+                .checked_add_unsigned(rhs.as_u64())
+                .expect("overflow when adding durations"),
+        )
+    }
+}
+
+impl AddAssign<Duration> for SignedDuration {
+    #[inline]
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn sub(self, rhs: Duration) -> SignedDuration {
+        SignedDuration(
+            self.0
+                .checked_sub_unsigned(rhs.as_u64())
+                .expect("overflow when subtracting durations"),
+        )
+    }
+}
+
+impl SubAssign<Duration> for SignedDuration {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<i32> for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn mul(self, rhs: i32) -> SignedDuration {
+        SignedDuration(self.0 * rhs as i64)
+    }
+}
+
+impl Div<i32> for SignedDuration {
+    type Output = SignedDuration;
+
+    #[inline]
+    fn div(self, rhs: i32) -> SignedDuration {
+        SignedDuration(self.0 / rhs as i64)
+    }
+}
+
+impl TryFrom<Duration> for SignedDuration {
+    type Error = TryFromDurationError;
+
+    #[inline]
+    fn try_from(duration: Duration) -> Result<SignedDuration, TryFromDurationError> {
+        i64::try_from(duration.as_u64())
+            .map(SignedDuration)
+            .map_err(|_| TryFromDurationError(()))
+    }
+}
+
+impl TryFrom<SignedDuration> for Duration {
+    type Error = TryFromDurationError;
+
+    #[inline]
+    fn try_from(duration: SignedDuration) -> Result<Duration, TryFromDurationError> {
+        u64::try_from(duration.0)
+            .map(Duration::from_u64)
+            .map_err(|_| TryFromDurationError(()))
+    }
+}
+
+impl fmt::Display for SignedDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.unsigned_abs().fmt_human(f, self.is_negative(), false)
+    }
+}
+
+impl fmt::Debug for SignedDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.unsigned_abs().fmt_human(f, self.is_negative(), true)
+    }
+}
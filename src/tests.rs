@@ -1,12 +1,26 @@
+use std::sync::{Mutex, MutexGuard};
 use std::thread::sleep;
 use std::time;
 
+#[cfg(feature = "mock")]
+use super::MockClock;
+use super::{
+    BootInstant, Clock, ClockSource, CoarseClock, Date, Duration, HttpDate, Instant,
+    ParseDurationErrorKind, SecondsFormat, SignedDuration, TimeSnapshot,
+};
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
-use super::Updater;
-use super::{Clock, Duration, Instant};
+use super::{Caches, Updater, UpdaterStats};
+
+// Tests changing the process-wide clock state must not run concurrently
+static GLOBAL_STATE: Mutex<()> = Mutex::new(());
+
+fn lock_global_state() -> MutexGuard<'static, ()> {
+    GLOBAL_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
 
 #[test]
 fn tests() {
+    let lock = lock_global_state();
     let ts = Instant::now();
     let d = Duration::from_secs(2);
     sleep(time::Duration::new(3, 0));
@@ -27,21 +41,34 @@ fn tests() {
     assert_eq!(Clock::recent_since_epoch(), clock_now);
     assert!(Clock::now_since_epoch() > clock_now);
 
+    drop(lock);
     #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
     tests_updater();
 }
 
+#[test]
+fn tests_precise_clock() {
+    let _lock = lock_global_state();
+    let clock_now = Clock::now_since_epoch();
+    sleep(time::Duration::from_millis(10));
+    assert!(Clock::now_since_epoch_precise() > clock_now);
+    assert!(Clock::now_precise() >= Clock::recent());
+}
+
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 #[test]
 fn tests_updater() {
+    let _lock = lock_global_state();
     let updater = Updater::new(250)
         .start()
         .expect("Unable to start a background updater");
     let ts = Instant::recent();
+    let boot_ts = BootInstant::recent();
     let clock_recent = Clock::recent_since_epoch();
     sleep(time::Duration::new(2, 0));
     assert!(Clock::recent_since_epoch() > clock_recent);
     assert!(Instant::recent() != ts);
+    assert!(BootInstant::recent() > boot_ts);
     updater.stop().unwrap();
     let clock_recent = Clock::recent_since_epoch();
     sleep(time::Duration::new(1, 0));
@@ -53,3 +80,794 @@ fn tests_duration() {
     let duration = Duration::from_days(1000);
     assert_eq!(duration.as_days(), 1000);
 }
+
+#[test]
+fn tests_display() {
+    let duration = Duration::new(3_723, 250_000_000);
+    assert_eq!(duration.to_string(), "1h 2m 3.250s");
+    assert_eq!(format!("{:.1}", duration), "1h 2m 3.3s");
+    assert_eq!(format!("{:.0}", duration), "1h 2m 3s");
+    assert_eq!(format!("{:>14}", duration), "  1h 2m 3.250s");
+    assert_eq!(format!("{:-<8.0}|", Duration::from_secs(0)), "0s------|");
+    assert_eq!(Duration::from_days(2).to_string(), "2d 0h 0m 0.000s");
+    assert_eq!(
+        format!("{:.6}", Duration::new(59, 999_999_700)),
+        "1m 0.000000s"
+    );
+    assert_eq!(format!("{:?}", Duration::new(1, 500_000_000)), "1.5s");
+    assert_eq!(format!("{:?}", Duration::from_secs(60)), "1m 0s");
+
+    let instant = Instant::from_ticks(Duration::from_secs(90).as_ticks());
+    assert_eq!(instant.to_string(), "1m 30.000s");
+    assert_eq!(format!("{:?}", instant), "Instant(1m 30s)");
+
+    let date = Date::from_unix_timestamp(Duration::new(1_700_000_000, 123_456_789));
+    assert_eq!(date.to_string(), "2023-11-14T22:13:20Z");
+    assert_eq!(format!("{:.3}", date), "2023-11-14T22:13:20.123Z");
+    assert_eq!(format!("{:.6}", date), "2023-11-14T22:13:20.123456Z");
+    assert_eq!(format!("{:?}", date), "Date(2023-11-14T22:13:20.123456Z)");
+}
+
+#[test]
+fn tests_parse_duration() {
+    for (s, expected) in [
+        ("250ms", Duration::new(0, 250_000_000)),
+        ("2.5s", Duration::new(2, 500_000_000)),
+        ("1h30m", Duration::from_mins(90)),
+        ("1h 2m 3.250s", Duration::new(3_723, 250_000_000)),
+        (" 1d 12h ", Duration::from_hours(36)),
+        ("1.5µs", Duration::new(0, 1_500)),
+        ("10us5ns", Duration::new(0, 10_005)),
+        (".5m", Duration::from_secs(30)),
+        ("0", Duration::from_secs(0)),
+    ] {
+        assert_eq!(s.parse::<Duration>().unwrap(), expected, "{}", s);
+    }
+    for (s, kind, position) in [
+        ("", ParseDurationErrorKind::Empty, 0),
+        ("1h x", ParseDurationErrorKind::InvalidNumber, 3),
+        ("10", ParseDurationErrorKind::MissingUnit, 2),
+        ("5 parsecs", ParseDurationErrorKind::UnknownUnit, 2),
+        ("1s 5000000000d", ParseDurationErrorKind::Overflow, 3),
+    ] {
+        let err = s.parse::<Duration>().unwrap_err();
+        assert_eq!((err.kind(), err.position()), (kind, position), "{}", s);
+    }
+    for duration in [
+        Duration::from_secs(0),
+        Duration::new(1, 5),
+        Duration::new(90_061, 999_999_999),
+        Duration::from_days(40_000),
+        Duration::MAX,
+    ] {
+        let s = format!("{:#}", duration);
+        assert_eq!(s.parse::<Duration>().unwrap(), duration, "{}", s);
+        let s = format!("{:.3}", duration);
+        assert_eq!(format!("{:.3}", s.parse::<Duration>().unwrap()), s);
+    }
+}
+
+#[test]
+fn tests_signed_duration() {
+    use std::convert::TryFrom;
+
+    let earlier = Instant::from_ticks(Duration::from_secs(100).as_ticks());
+    let later = earlier + Duration::from_secs(5);
+    assert_eq!(
+        later.signed_duration_since(earlier),
+        SignedDuration::from_secs(5)
+    );
+    let slack = earlier.signed_duration_since(later);
+    assert_eq!(slack, SignedDuration::from_secs(-5));
+    assert!(slack.is_negative());
+    assert_eq!(slack.signum(), -1);
+    assert_eq!(slack.abs(), -slack);
+    assert_eq!(slack.unsigned_abs(), Duration::from_secs(5));
+    assert!(Duration::try_from(slack).is_err());
+    assert_eq!(Duration::try_from(-slack).unwrap(), Duration::from_secs(5));
+    assert_eq!(
+        SignedDuration::try_from(Duration::from_secs(5)).unwrap(),
+        -slack
+    );
+
+    let slack = slack + Duration::new(3, 500_000_000);
+    assert_eq!((slack.as_secs(), slack.subsec_nanos()), (-1, -500_000_000));
+    assert_eq!(slack.as_f64(), -1.5);
+    assert_eq!(slack.to_string(), "-1.500s");
+    assert_eq!(format!("{:?}", slack - Duration::from_secs(60)), "-1m 1.5s");
+    assert_eq!(SignedDuration::ZERO.signum(), 0);
+    let min = SignedDuration::from_i64(i64::MIN);
+    assert_eq!(-min, SignedDuration::from_i64(i64::MAX));
+    assert_eq!(-min, min.abs());
+
+    let date = Date::from_unix_secs(1_700_000_000);
+    assert_eq!(
+        date.signed_duration_since(date + Duration::from_secs(1)),
+        SignedDuration::from_secs(-1)
+    );
+}
+
+#[test]
+fn tests_duration_std_parity() {
+    // Whole seconds are exact, fractions are rounded down to 2^-32s
+    assert_eq!(
+        Duration::from_micros(1_500_000),
+        Duration::new(1, 500_000_000)
+    );
+    assert_eq!(Duration::from_nanos(2_000_000_123), Duration::new(2, 123));
+    assert_eq!(Duration::from_nanos(1).as_nanos(), 0);
+    assert_eq!(Duration::from_nanos(1_000).as_nanos(), 999);
+    assert_eq!(Duration::from_secs(7).as_nanos(), 7_000_000_000);
+
+    // A millisecond is approximated as 1/1024s
+    assert_eq!(Duration::from_millis(3_000), Duration::from_secs(3));
+    assert_eq!(Duration::from_millis(512), Duration::new(0, 500_000_000));
+    assert!(Duration::from_millis(999) < Duration::new(0, 999_000_000));
+
+    // Floats are rounded to the nearest representable value
+    assert_eq!(Duration::from_secs_f64(1.5), Duration::new(1, 500_000_000));
+    assert_eq!(Duration::from_secs_f32(0.25), Duration::new(0, 250_000_000));
+    assert_eq!(
+        Duration::from_secs_f64(1.0 / (1u64 << 33) as f64).as_u64(),
+        1
+    );
+    assert_eq!(Duration::new(2, 500_000_000).as_secs_f64(), 2.5);
+    assert_eq!(Duration::new(2, 500_000_000).as_secs_f32(), 2.5);
+    assert!(std::panic::catch_unwind(|| Duration::from_secs_f64(-1.0)).is_err());
+    assert!(std::panic::catch_unwind(|| Duration::from_secs_f64(f64::NAN)).is_err());
+    assert!(std::panic::catch_unwind(|| Duration::from_secs_f64(1e10)).is_err());
+
+    let d = Duration::from_secs(3);
+    assert_eq!(d.mul_f64(1.5), Duration::new(4, 500_000_000));
+    assert_eq!(d.mul_f32(2.0), Duration::from_secs(6));
+    assert_eq!(d.div_f64(4.0), Duration::new(0, 750_000_000));
+    assert_eq!(d.div_f32(0.5), Duration::from_secs(6));
+    assert_eq!(d.div_duration_f64(Duration::from_secs(2)), 1.5);
+    assert_eq!(d.div_duration_f32(Duration::from_secs(4)), 0.75);
+
+    assert!(Duration::ZERO.is_zero());
+    assert!(!Duration::MAX.is_zero());
+    assert_eq!(Duration::default(), Duration::ZERO);
+    assert_eq!(Duration::MAX.as_secs(), u32::MAX as u64);
+    assert_eq!(
+        d.checked_rem(Duration::from_secs(2)),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(d.checked_rem(Duration::ZERO), None);
+    assert_eq!(d % Duration::from_secs(2), Duration::from_secs(1));
+    assert_eq!(2 * d, Duration::from_secs(6));
+
+    let durations = [Duration::from_secs(1), Duration::new(0, 500_000_000)];
+    assert_eq!(
+        durations.iter().sum::<Duration>(),
+        Duration::new(1, 500_000_000)
+    );
+    assert_eq!(
+        durations.iter().copied().sum::<Duration>(),
+        Duration::new(1, 500_000_000)
+    );
+    assert_eq!(
+        std::iter::empty::<Duration>().sum::<Duration>(),
+        Duration::ZERO
+    );
+}
+
+#[test]
+fn tests_rounding() {
+    let d = Duration::new(1, 260_000_000);
+    let tenth = Duration::new(0, 100_000_000);
+    // A tenth of a second is not exactly representable, so results are
+    // multiples of its rounded value
+    assert_eq!(d.truncate_to(tenth), tenth * 12);
+    assert_eq!(d.ceil_to(tenth), tenth * 13);
+    assert_eq!(d.round_to(tenth), tenth * 13);
+    assert_eq!(d.round_to(tenth).to_string(), "1.300s");
+    assert_eq!(Duration::from_secs(1).round_to(tenth).to_string(), "1.000s");
+
+    // `from_millis()` is approximate, and so are the granularities it builds
+    let approx_tenth = Duration::from_millis(100);
+    assert!(approx_tenth > Duration::new(0, 97_600_000));
+    assert!(approx_tenth < Duration::new(0, 97_700_000));
+    assert_eq!(
+        Duration::from_millis(250).truncate_to(approx_tenth),
+        Duration::from_millis(200)
+    );
+    assert_eq!(
+        Duration::from_secs(1).round_to(approx_tenth),
+        approx_tenth * 10
+    );
+    assert_eq!(
+        Duration::from_secs(1).round_to(approx_tenth).to_string(),
+        "0.977s"
+    );
+    assert_eq!(d.round_to(Duration::from_secs(1)), Duration::from_secs(1));
+    assert_eq!(
+        Duration::new(2, 500_000_000).round_to(Duration::from_secs(1)),
+        Duration::from_secs(3)
+    );
+
+    // Power-of-two granularities are masked
+    let half = Duration::from_millis(512);
+    assert_eq!(d.truncate_to(half), Duration::from_secs(1));
+    assert_eq!(d.ceil_to(half), Duration::new(1, 500_000_000));
+    assert_eq!(d.round_to(half), Duration::new(1, 500_000_000));
+
+    // Multiples are left untouched
+    let multiple = tenth * 7;
+    assert_eq!(multiple.truncate_to(tenth), multiple);
+    assert_eq!(multiple.ceil_to(tenth), multiple);
+    assert_eq!(multiple.round_to(tenth), multiple);
+    assert_eq!(Duration::ZERO.ceil_to(half), Duration::ZERO);
+    assert!(std::panic::catch_unwind(|| d.truncate_to(Duration::ZERO)).is_err());
+    assert!(std::panic::catch_unwind(|| Duration::MAX.ceil_to(tenth)).is_err());
+
+    let period = Duration::from_secs(60);
+    let instant = Instant::from_ticks(Duration::new(3_605, 250_000_000).as_ticks());
+    let bucket = instant.align_down(period);
+    assert_eq!(
+        bucket,
+        Instant::from_ticks(Duration::from_secs(3_600).as_ticks())
+    );
+    assert_eq!(bucket.align_down(period), bucket);
+    assert_eq!((instant + period).align_down(period), bucket + period);
+}
+
+#[test]
+fn tests_clock_source() {
+    let _lock = lock_global_state();
+    assert_eq!(Instant::clock_source(), ClockSource::Coarse);
+    for source in [
+        ClockSource::Monotonic,
+        ClockSource::MonotonicRaw,
+        ClockSource::Boottime,
+        ClockSource::Coarse,
+    ] {
+        Instant::set_clock_source(source);
+        assert_eq!(Instant::clock_source(), source);
+        // The snapshot follows the new clock, even if it is behind
+        assert_eq!(TimeSnapshot::recent().instant(), Instant::recent());
+        let ts = Instant::now();
+        sleep(time::Duration::from_millis(50));
+        assert!(Instant::now() > ts);
+    }
+}
+
+#[test]
+fn tests_recent_within() {
+    let _lock = lock_global_state();
+    Instant::update();
+    let recent = Instant::recent();
+    sleep(time::Duration::from_millis(100));
+    assert!(Instant::cache_age().unwrap() >= Duration::from_millis(50));
+    assert_eq!(Instant::recent_within(Duration::from_secs(60)), recent);
+    let fresh = Instant::recent_within(Duration::from_millis(10));
+    assert!(fresh > recent);
+    assert_eq!(Instant::recent(), fresh);
+    assert!(Instant::cache_age().unwrap() < Duration::from_millis(50));
+}
+
+#[test]
+fn tests_coarse_clock() {
+    static CLOCK: CoarseClock = CoarseClock::new();
+
+    let _lock = lock_global_state();
+    assert!(CoarseClock::global().is_global());
+    assert!(!CLOCK.is_global());
+    // The cached values are lazily initialized
+    assert!(CLOCK.recent_since_epoch() > Duration::from_secs(1_600_000_000));
+    CLOCK.update();
+    assert_eq!(
+        CLOCK.recent_snapshot().since_epoch(),
+        CLOCK.recent_since_epoch()
+    );
+    CLOCK.set_recent_since_epoch(Duration::from_secs(1_000));
+    assert_eq!(CLOCK.recent_date(), Date::from_unix_secs(1_000));
+    assert_ne!(Clock::recent_since_epoch(), Duration::from_secs(1_000));
+
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    {
+        let ts = CLOCK.recent();
+        let updater = Updater::builder()
+            .period(Duration::from_millis(10))
+            .clock(&CLOCK)
+            .build()
+            .unwrap()
+            .start()
+            .unwrap();
+        sleep(time::Duration::from_millis(100));
+        updater.stop().unwrap();
+        assert!(CLOCK.recent() > ts);
+        assert!(CLOCK.recent_since_epoch() > Duration::from_secs(1_600_000_000));
+    }
+}
+
+#[test]
+fn tests_time_snapshot() {
+    let _lock = lock_global_state();
+    #[cfg(feature = "mock")]
+    {
+        MockClock::set(
+            Instant::from_ticks(1 << 40),
+            Duration::from_secs(1_700_000_000),
+        );
+        let snapshot = TimeSnapshot::recent();
+        assert_eq!(snapshot.instant(), Instant::from_ticks(1 << 40));
+        assert_eq!(snapshot.date(), Date::from_unix_secs(1_700_000_000));
+        MockClock::advance(Duration::from_secs(1));
+        let snapshot = TimeSnapshot::recent();
+        assert_eq!(snapshot.instant(), Instant::recent());
+        assert_eq!(snapshot.since_epoch(), Clock::recent_since_epoch());
+        MockClock::unfreeze();
+    }
+
+    TimeSnapshot::update();
+    let snapshot = TimeSnapshot::recent();
+    assert!(snapshot.instant() <= Instant::now());
+    assert!(snapshot.since_epoch() <= Clock::now_since_epoch());
+}
+
+#[test]
+fn tests_monotonic_cache() {
+    let _lock = lock_global_state();
+    Instant::set_clock_source(ClockSource::Monotonic);
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            std::thread::spawn(|| {
+                let mut instant = Instant::recent();
+                let mut since_epoch = Clock::recent_since_epoch();
+                for _ in 0..100_000 {
+                    Instant::update();
+                    Clock::update();
+                    let recent = Instant::recent();
+                    let recent_since_epoch = Clock::recent_since_epoch();
+                    assert!(recent >= instant);
+                    assert!(recent_since_epoch >= since_epoch);
+                    instant = recent;
+                    since_epoch = recent_since_epoch;
+                }
+            })
+        })
+        .collect();
+    for th in threads {
+        th.join().unwrap();
+    }
+    Instant::set_clock_source(ClockSource::Coarse);
+}
+
+#[test]
+fn tests_refresh_every() {
+    let _lock = lock_global_state();
+    assert_eq!(Instant::refresh_every(), 0);
+    Instant::set_refresh_every(3);
+    Clock::set_refresh_every(3);
+    Instant::update();
+    Clock::update();
+    let ts = Instant::now_without_cache_update();
+    let since_epoch = Clock::now_since_epoch_precise();
+    sleep(time::Duration::from_millis(50));
+    assert!(Instant::recent() <= ts);
+    assert!(Instant::recent() <= ts);
+    assert!(Instant::recent() > ts);
+    assert!(Clock::recent_since_epoch() <= since_epoch);
+    assert!(Clock::recent_since_epoch() <= since_epoch);
+    assert!(Clock::recent_since_epoch() > since_epoch);
+    Instant::set_refresh_every(0);
+    Clock::set_refresh_every(0);
+}
+
+#[test]
+fn tests_boot_instant() {
+    let _lock = lock_global_state();
+    let ts = BootInstant::now();
+    sleep(time::Duration::from_millis(100));
+    assert!(ts.elapsed() >= Duration::from_millis(50));
+    assert_eq!(BootInstant::from_ticks(ts.as_ticks()), ts);
+
+    let boot_time = Clock::boot_time();
+    assert!(boot_time > Duration::from_secs(0));
+    assert!(boot_time < Clock::now_since_epoch());
+}
+
+#[cfg(feature = "tsc")]
+#[test]
+fn tests_tsc() {
+    use super::TscInstant;
+
+    let _lock = lock_global_state();
+    let ts = TscInstant::now();
+    let mono = Instant::now_without_cache_update();
+    sleep(time::Duration::from_millis(100));
+    TscInstant::recalibrate();
+    let elapsed = ts.elapsed();
+    let mono_elapsed = mono.elapsed();
+    assert!(elapsed >= Duration::from_millis(90));
+    assert!(elapsed.abs_diff(mono_elapsed) < Duration::from_millis(20));
+    assert!(TscInstant::now() >= ts);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn tests_mock() {
+    let _lock = lock_global_state();
+    MockClock::freeze();
+    let ts = Instant::now();
+    let clock_now = Clock::now_since_epoch();
+    assert_eq!(Instant::recent(), ts);
+    assert_eq!(Clock::recent_since_epoch(), clock_now);
+
+    MockClock::advance(Duration::from_secs(5));
+    assert_eq!(Instant::recent() - ts, Duration::from_secs(5));
+    assert_eq!(ts.elapsed(), Duration::from_secs(5));
+    assert_eq!(
+        Clock::recent_since_epoch() - clock_now,
+        Duration::from_secs(5)
+    );
+    assert_eq!(Clock::now_since_epoch() - clock_now, Duration::from_secs(5));
+
+    MockClock::set_since_epoch(Duration::from_secs(1_000_000_000));
+    assert_eq!(
+        Clock::recent_since_epoch(),
+        Duration::from_secs(1_000_000_000)
+    );
+    MockClock::set_instant(ts);
+    assert_eq!(Instant::now(), ts);
+
+    MockClock::unfreeze();
+    assert!(!MockClock::is_frozen());
+    assert!(Clock::now_since_epoch() > Duration::from_secs(1_000_000_000));
+}
+
+#[test]
+fn tests_date() {
+    use std::convert::TryFrom;
+
+    let _lock = lock_global_state();
+    let date = Date::from_unix_secs(1_700_000_000);
+    assert_eq!(date.as_unix_secs(), 1_700_000_000);
+    assert_eq!(date.as_unix_millis(), 1_700_000_000_000);
+    assert_eq!(
+        Date::from_unix_millis(1_700_000_000_250).as_unix_millis(),
+        1_700_000_000_250
+    );
+
+    let later = date + Duration::from_secs(90);
+    assert_eq!(later - date, Duration::from_secs(90));
+    assert_eq!(date - later, Duration::from_secs(0));
+    assert_eq!(date.checked_duration_since(later), None);
+    assert_eq!(later - Duration::from_secs(90), date);
+
+    let system_time = time::SystemTime::from(later);
+    assert_eq!(Date::try_from(system_time).unwrap(), later);
+    assert!(Date::try_from(time::UNIX_EPOCH - time::Duration::from_secs(1)).is_err());
+
+    assert!(Clock::now() > date);
+}
+
+#[test]
+fn tests_rfc3339() {
+    let date = Date::from_unix_timestamp(Duration::new(1_709_251_199, 123_456_789));
+    assert_eq!(
+        &*date.to_rfc3339(SecondsFormat::Secs),
+        "2024-02-29T23:59:59Z"
+    );
+    assert_eq!(
+        date.to_rfc3339(SecondsFormat::Millis).as_str(),
+        "2024-02-29T23:59:59.123Z"
+    );
+    assert_eq!(
+        date.to_rfc3339(SecondsFormat::Micros).to_string(),
+        "2024-02-29T23:59:59.123456Z"
+    );
+    assert_eq!(
+        Date::UNIX_EPOCH.to_rfc3339(SecondsFormat::Secs).as_str(),
+        "1970-01-01T00:00:00Z"
+    );
+
+    assert_eq!(
+        Date::parse_rfc3339("2024-02-29T23:59:59.123456789Z"),
+        Ok(date)
+    );
+    assert_eq!(
+        "2024-03-01T01:29:59.123456789+01:30".parse::<Date>(),
+        Ok(date)
+    );
+    assert_eq!(
+        Date::parse_rfc3339("2024-02-29t20:59:59-03:00"),
+        Ok(Date::from_unix_secs(1_709_251_199))
+    );
+    for invalid in [
+        "",
+        "2024-02-29",
+        "2023-02-29T00:00:00Z",
+        "2024-02-29 23:59:59Z",
+        "2024-02-29T23:59:60Z",
+        "2024-02-29T23:59:59",
+        "2024-02-29T23:59:59.Z",
+        "2024-02-29T23:59:59+0100",
+        "1969-12-31T23:59:59Z",
+        "2024-02-29T23:59:59Z ",
+    ] {
+        assert!(Date::parse_rfc3339(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn tests_http_date() {
+    let date = Date::from_unix_secs(784_111_777);
+    let http_date = HttpDate::from(date);
+    assert_eq!(http_date.as_str(), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(HttpDate::parse(&http_date), Ok(date));
+    assert_eq!(HttpDate::parse("Sunday, 06-Nov-94 08:49:37 GMT"), Ok(date));
+    assert_eq!(HttpDate::parse("Sun Nov  6 08:49:37 1994"), Ok(date));
+    assert!(HttpDate::parse("Sun, 06 Nov 1994 08:49:37 UTC").is_err());
+    assert!(HttpDate::parse("Sun, 31 Nov 1994 08:49:37 GMT").is_err());
+    assert_eq!(
+        HttpDate::from(Date::from_unix_secs(951_782_400)).as_str(),
+        "Tue, 29 Feb 2000 00:00:00 GMT"
+    );
+
+    let _lock = lock_global_state();
+    Clock::update();
+    let recent = HttpDate::parse(&HttpDate::recent()).unwrap();
+    assert_eq!(recent.as_unix_secs(), Clock::recent().as_unix_secs());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn tests_serde() {
+    use serde_json::{from_str, json, to_value};
+
+    let duration = Duration::new(2, 500_000_000);
+    assert_eq!(
+        to_value(duration).unwrap(),
+        json!({"secs": 2, "nanos": 500_000_000})
+    );
+    assert_eq!(
+        from_str::<Duration>(r#"{"secs":2,"nanos":500000000}"#).unwrap(),
+        duration
+    );
+    assert_eq!(from_str::<Duration>("[2,500000000]").unwrap(), duration);
+    assert!(from_str::<Duration>(r#"{"secs":2}"#).is_err());
+
+    let mut serializer = serde_json::Serializer::new(Vec::new());
+    super::serde::secs_f64::serialize(&duration, &mut serializer).unwrap();
+    assert_eq!(serializer.into_inner(), b"2.5");
+    let mut deserializer = serde_json::Deserializer::from_str("2.5");
+    assert_eq!(
+        super::serde::secs_f64::deserialize(&mut deserializer).unwrap(),
+        duration
+    );
+
+    let mut serializer = serde_json::Serializer::new(Vec::new());
+    super::serde::millis::serialize(&duration, &mut serializer).unwrap();
+    assert_eq!(serializer.into_inner(), b"2500");
+    let mut deserializer = serde_json::Deserializer::from_str("2500");
+    assert_eq!(
+        super::serde::millis::deserialize(&mut deserializer).unwrap(),
+        duration
+    );
+    let mut deserializer = serde_json::Deserializer::from_str("4294967296000");
+    assert!(super::serde::millis::deserialize(&mut deserializer).is_err());
+
+    let mut serializer = serde_json::Serializer::new(Vec::new());
+    super::serde::human::serialize(&duration, &mut serializer).unwrap();
+    assert_eq!(serializer.into_inner(), b"\"2.5s\"");
+    for (s, expected) in [
+        ("\"2.5s\"", duration),
+        ("\"2500ms\"", duration),
+        ("\"1.5m\"", Duration::from_secs(90)),
+    ] {
+        let mut deserializer = serde_json::Deserializer::from_str(s);
+        assert_eq!(
+            super::serde::human::deserialize(&mut deserializer).unwrap(),
+            expected
+        );
+    }
+    let mut deserializer = serde_json::Deserializer::from_str("\"2.5\"");
+    assert!(super::serde::human::deserialize(&mut deserializer).is_err());
+    // The serialized format is stable, and round-trips
+    for (duration, s) in [
+        (Duration::from_secs(90), "\"90s\""),
+        (Duration::from_days(1), "\"86400s\""),
+        (Duration::new(3_723, 250_000_000), "\"3723.25s\""),
+        (Duration::from_secs(0), "\"0s\""),
+    ] {
+        let mut serializer = serde_json::Serializer::new(Vec::new());
+        super::serde::human::serialize(&duration, &mut serializer).unwrap();
+        assert_eq!(serializer.into_inner(), s.as_bytes());
+        let mut deserializer = serde_json::Deserializer::from_str(s);
+        assert_eq!(
+            super::serde::human::deserialize(&mut deserializer).unwrap(),
+            duration
+        );
+    }
+    let mut deserializer = serde_json::Deserializer::from_str("\"1m 30s\"");
+    assert_eq!(
+        super::serde::human::deserialize(&mut deserializer).unwrap(),
+        Duration::from_secs(90)
+    );
+
+    let date = Date::from_unix_timestamp(Duration::new(1_700_000_000, 250_000_000));
+    let mut serializer = serde_json::Serializer::new(Vec::new());
+    super::serde::rfc3339::serialize(&date, &mut serializer).unwrap();
+    assert_eq!(serializer.into_inner(), b"\"2023-11-14T22:13:20.250000Z\"");
+    let mut deserializer = serde_json::Deserializer::from_str("\"2023-11-14T22:13:20.25Z\"");
+    assert_eq!(
+        super::serde::rfc3339::deserialize(&mut deserializer).unwrap(),
+        date
+    );
+}
+
+#[test]
+fn tests_resolution() {
+    let resolution = Instant::resolution();
+    assert!(resolution > Duration::from_u64(0));
+    assert!(resolution <= Duration::from_millis(100));
+    let resolution = Clock::resolution();
+    assert!(resolution > Duration::from_u64(0));
+    assert!(resolution <= Duration::from_secs(1));
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+#[test]
+fn tests_updater_builder() {
+    use std::io;
+
+    let _lock = lock_global_state();
+    let mut builder = Updater::builder()
+        .period(Duration::from_u64(Duration::from_millis(1).as_u64() / 2))
+        .name("coarsetime-test")
+        .stack_size(64 * 1024)
+        .caches(Caches::Instant);
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // Pin the thread to a CPU the test is allowed to run on
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        assert_eq!(unsafe { libc::sched_getaffinity(0, size, &mut set) }, 0);
+        let cpu = (0..libc::CPU_SETSIZE as usize)
+            .find(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+            .unwrap();
+        builder = builder.cpu_affinity(&[cpu]);
+    }
+    let updater = builder.build().unwrap().start().unwrap();
+    let ts = Instant::recent();
+    let clock_recent = Clock::recent_since_epoch();
+    sleep(time::Duration::from_millis(100));
+    assert!(Instant::recent() > ts);
+    assert_eq!(Clock::recent_since_epoch(), clock_recent);
+    updater.stop().unwrap();
+
+    let updater = Updater::builder()
+        .cpu_affinity(&[usize::MAX])
+        .build()
+        .unwrap();
+    assert!(updater.start().is_err());
+
+    let builder = Updater::builder().period(Duration::from_secs(0));
+    assert_eq!(
+        builder.build().unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+#[test]
+fn tests_updater_callbacks() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    let _lock = lock_global_state();
+    let ticks = Arc::new(AtomicU64::new(0));
+    let slow_ticks = Arc::new(AtomicU64::new(0));
+    let panics = Arc::new(AtomicU64::new(0));
+    let (ticks2, slow_ticks2, panics2) = (ticks.clone(), slow_ticks.clone(), panics.clone());
+    let updater = Updater::new(10)
+        .on_tick(move |_now| {
+            ticks2.fetch_add(1, Ordering::Relaxed);
+        })
+        .on_tick(move |_now| {
+            panics2.fetch_add(1, Ordering::Relaxed);
+            panic!("tick callback panic");
+        })
+        .on_tick_every(Duration::from_secs(10), move |_now| {
+            slow_ticks2.fetch_add(1, Ordering::Relaxed);
+        })
+        .start()
+        .unwrap();
+    sleep(time::Duration::from_millis(300));
+    updater.stop().unwrap();
+    assert!(ticks.load(Ordering::Relaxed) > 5);
+    assert_eq!(slow_ticks.load(Ordering::Relaxed), 1);
+    assert_eq!(panics.load(Ordering::Relaxed), 1);
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+#[test]
+fn tests_updater_stats() {
+    let _lock = lock_global_state();
+    let updater = Updater::new(10);
+    assert_eq!(updater.stats(), UpdaterStats::default());
+    let updater = updater.start().unwrap();
+    sleep(time::Duration::from_millis(305));
+    let stats = updater.stats();
+    updater.stop().unwrap();
+    // Deadlines are absolute, so the number of periods doesn't drift
+    assert!(stats.ticks > 5);
+    assert!(stats.ticks + stats.missed_ticks <= 31);
+    assert!(stats.max_lateness < Duration::from_secs(1));
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+#[test]
+fn tests_updater_guard() {
+    let _lock = lock_global_state();
+    assert!(Updater::new(10).stop().is_err());
+
+    let guard = Updater::new(10_000).start_guarded().unwrap();
+    assert_eq!(guard.period(), Duration::from_secs(10));
+    let started = time::Instant::now();
+    assert!(guard.set_period(Duration::from_secs(0)).is_err());
+    assert_eq!(guard.period(), Duration::from_secs(10));
+    guard.set_period(Duration::from_millis(10)).unwrap();
+    sleep(time::Duration::from_millis(200));
+    assert!(guard.stats().ticks > 5);
+    drop(guard);
+    // Stopping doesn't wait for the next deadline
+    assert!(started.elapsed() < time::Duration::from_secs(5));
+
+    let guard = Updater::new(10).start_guarded().unwrap();
+    guard.stop().unwrap();
+    assert!(Updater::new(0).start().is_err());
+}
+
+#[cfg(all(
+    any(feature = "tokio", feature = "smol"),
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+#[test]
+fn tests_async_updater() {
+    use std::io;
+
+    use super::AsyncUpdater;
+
+    let _lock = lock_global_state();
+    #[cfg(feature = "tokio")]
+    {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let updater = AsyncUpdater::new(10).start().unwrap();
+            let ts = Instant::recent();
+            let clock_recent = Clock::recent_since_epoch();
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+            assert!(Instant::recent() > ts);
+            assert!(Clock::recent_since_epoch() > clock_recent);
+            updater.stop().unwrap();
+            assert_eq!(
+                AsyncUpdater::new(0).start().unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        });
+        #[cfg(not(feature = "smol"))]
+        assert!(AsyncUpdater::new(10).start().is_err());
+    }
+    #[cfg(feature = "smol")]
+    {
+        let updater = AsyncUpdater::new(10)
+            .caches(Caches::Instant)
+            .start_smol()
+            .unwrap();
+        let ts = Instant::recent();
+        smol::block_on(smol::Timer::after(time::Duration::from_millis(200)));
+        assert!(Instant::recent() > ts);
+        updater.stop().unwrap();
+        assert_eq!(
+            AsyncUpdater::new(0).start_smol().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+    assert!(AsyncUpdater::new(10).stop().is_err());
+}
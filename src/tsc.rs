@@ -0,0 +1,209 @@
+use std::sync::Once;
+
+use super::duration::*;
+use super::helpers::*;
+use super::instant::*;
+
+/// A measurement of a *monotonically* increasing clock, computed from the
+/// CPU time stamp counter.
+/// Opaque and useful only with `Duration`.
+///
+/// Reading the time stamp counter doesn't require a system call nor a vDSO
+/// call. The counter is calibrated against `CLOCK_MONOTONIC` on first use or
+/// by `TscInstant::calibrate()`, and re-calibrated by the `Updater`.
+///
+/// This is only available on x86_64 Linux systems with an invariant time
+/// stamp counter. Everywhere else, `TscInstant` falls back to the same clock
+/// as `Instant`. `TscInstant::is_tsc_enabled()` tells which one is in use.
+///
+/// Its `Display` and `Debug` output are the same as for `Instant`.
+#[derive(Copy, Clone, Hash, Ord, Eq, PartialOrd, PartialEq)]
+pub struct TscInstant(u64);
+
+static INIT: Once = Once::new();
+
+impl_instant!(TscInstant, "a");
+
+impl TscInstant {
+    /// Returns an instant corresponding to "now"
+    ///
+    /// The first call calibrates the time stamp counter, which takes about
+    /// 10 milliseconds, unless `calibrate()` was called before.
+    #[inline]
+    pub fn now() -> TscInstant {
+        Self::calibrate();
+        TscInstant(arch::now().unwrap_or_else(Instant::_os_now))
+    }
+
+    /// Calibrates the time stamp counter against the system clock, if this
+    /// hasn't been done yet.
+    ///
+    /// This blocks the calling thread for about 10 milliseconds. It is called
+    /// by `Updater::start()`, and can be called at startup to keep the delay
+    /// out of the first `now()` call.
+    pub fn calibrate() {
+        INIT.call_once(arch::calibrate);
+    }
+
+    /// Returns `true` if instants are computed using the time stamp counter,
+    /// and `false` if the regular clock is used as a fallback
+    pub fn is_tsc_enabled() -> bool {
+        Self::calibrate();
+        arch::is_enabled()
+    }
+
+    /// Re-calibrates the time stamp counter against the system clock.
+    ///
+    /// This function is called periodically by the `Updater`, and does nothing
+    /// if the time stamp counter hasn't been calibrated yet.
+    pub fn recalibrate() {
+        if INIT.is_completed() {
+            arch::recalibrate();
+        }
+    }
+
+    /// Returns the amount of time elapsed since this instant was created
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        Self::now() - *self
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod arch {
+    use std::arch::x86_64::{__cpuid, _rdtsc};
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time;
+
+    use crate::helpers::*;
+    use crate::seqlock::SeqLock;
+
+    // Base counter value, base instant, multiplier (instant units per
+    // counter tick, as a 32.32 fixed-point number), and the initial
+    // calibration point used to compute the long-term multiplier.
+    static CALIBRATION: SeqLock<5> = SeqLock::new();
+    static ENABLED: AtomicBool = AtomicBool::new(false);
+
+    const CALIBRATION_PERIOD: time::Duration = time::Duration::from_millis(10);
+
+    #[inline]
+    fn rdtsc() -> u64 {
+        unsafe { _rdtsc() }
+    }
+
+    fn monotonic() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_gettime(libc::CLOCK_MONOTONIC, tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        _timespec_to_u64(tp.tv_sec as u64, tp.tv_nsec as u32)
+    }
+
+    // Reads the counter on both sides of the system clock, to pair the clock
+    // value with the counter value at the middle of the call.
+    fn sample() -> (u64, u64) {
+        let tsc1 = rdtsc();
+        let mono = monotonic();
+        let tsc2 = rdtsc();
+        (tsc1 + (tsc2.wrapping_sub(tsc1) / 2), mono)
+    }
+
+    // `__cpuid()` is only safe to call on recent Rust versions
+    #[allow(unused_unsafe)]
+    fn is_invariant() -> bool {
+        let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+        if max_extended_leaf >= 0x8000_0007 && unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0 {
+            return true;
+        }
+        // Hypervisors may hide the CPUID bit, but still expose the flags
+        fs::read_to_string("/proc/cpuinfo")
+            .ok()
+            .and_then(|cpuinfo| {
+                cpuinfo
+                    .lines()
+                    .find(|line| line.starts_with("flags"))
+                    .map(|flags| {
+                        let mut flags = flags.split_whitespace();
+                        let constant = flags.clone().any(|flag| flag == "constant_tsc");
+                        constant && flags.any(|flag| flag == "nonstop_tsc")
+                    })
+            })
+            .unwrap_or(false)
+    }
+
+    #[inline]
+    fn convert(calibration: &[u64; 5], tsc: u64) -> u64 {
+        let [base_tsc, base, mult, _, _] = *calibration;
+        let delta = tsc.saturating_sub(base_tsc);
+        base.saturating_add(((delta as u128 * mult as u128) >> 32) as u64)
+    }
+
+    fn multiplier(tsc0: u64, mono0: u64, tsc1: u64, mono1: u64) -> Option<u64> {
+        if tsc1 <= tsc0 || mono1 <= mono0 {
+            return None;
+        }
+        let mult = (((mono1 - mono0) as u128) << 32) / (tsc1 - tsc0) as u128;
+        u64::try_from(mult).ok().filter(|&mult| mult != 0)
+    }
+
+    pub(super) fn calibrate() {
+        if !is_invariant() {
+            return;
+        }
+        let (tsc0, mono0) = sample();
+        thread::sleep(CALIBRATION_PERIOD);
+        let (tsc1, mono1) = sample();
+        if let Some(mult) = multiplier(tsc0, mono0, tsc1, mono1) {
+            CALIBRATION.try_write([tsc1, mono1, mult, tsc0, mono0]);
+            ENABLED.store(true, Ordering::Release);
+        }
+    }
+
+    pub(super) fn recalibrate() {
+        if !is_enabled() {
+            return;
+        }
+        let calibration = CALIBRATION.read();
+        let [_, _, mult, tsc0, mono0] = calibration;
+        let (tsc, mono) = sample();
+        let mult = multiplier(tsc0, mono0, tsc, mono).unwrap_or(mult);
+        // Never move backwards from what readers may already have observed
+        let base = convert(&calibration, tsc).max(mono);
+        CALIBRATION.try_write([tsc, base, mult, tsc0, mono0]);
+    }
+
+    #[inline]
+    pub(super) fn is_enabled() -> bool {
+        ENABLED.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(super) fn now() -> Option<u64> {
+        if !is_enabled() {
+            return None;
+        }
+        Some(convert(&CALIBRATION.read(), rdtsc()))
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+mod arch {
+    pub(super) fn calibrate() {}
+
+    pub(super) fn recalibrate() {}
+
+    #[inline]
+    pub(super) fn is_enabled() -> bool {
+        false
+    }
+
+    #[inline]
+    pub(super) fn now() -> Option<u64> {
+        None
+    }
+}
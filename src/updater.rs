@@ -1,60 +1,653 @@
+use std::convert::TryFrom;
+use std::fmt;
 use std::io;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::mem;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time;
 
-use super::clock::*;
+use super::boot_instant::*;
+use super::coarse_clock::*;
+use super::duration::*;
 use super::instant::*;
+#[cfg(feature = "tsc")]
+use super::tsc::*;
 
-/// A service to periodically call `Instant::update()`
+const DEFAULT_PERIOD: time::Duration = time::Duration::from_millis(250);
+const DEFAULT_THREAD_NAME: &str = "coarsetime";
+
+/// The cached values refreshed by an `Updater`
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub enum Caches {
+    /// `Instant` and `BootInstant` only
+    Instant,
+    /// `Clock` (and thus `HttpDate`) only
+    Clock,
+    /// Both the monotonic clocks and the system clock
+    #[default]
+    Both,
+}
+
+/// The scheduling priority of the `Updater` thread
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum ThreadPriority {
+    /// A real-time priority (`SCHED_FIFO`), usually from 1 (lowest) to 99
+    /// (highest). Only supported on Unix-like systems.
+    RealTime(u8),
+    /// A nice value, from -20 (highest priority) to 19 (lowest priority).
+    /// Only supported on Linux and Android.
+    Nice(i8),
+}
+
+/// A service to periodically call `Instant::update()`, `BootInstant::update()`
+/// and `Clock::update()`
+///
+/// Dropping a running `Updater` doesn't stop the updates. Use `stop()`, or
+/// `start_guarded()` to stop them automatically.
 #[derive(Debug)]
 pub struct Updater {
-    period: time::Duration,
-    running: Arc<AtomicBool>,
+    name: String,
+    stack_size: Option<usize>,
+    cpus: Option<Vec<usize>>,
+    priority: Option<ThreadPriority>,
+    caches: Caches,
+    clock: &'static CoarseClock,
+    callbacks: Vec<Callback>,
+    shared: Arc<Shared>,
     th: Option<thread::JoinHandle<()>>,
 }
 
+/// An `Updater` that is stopped when dropped
+#[derive(Debug)]
+pub struct UpdaterGuard {
+    updater: Option<Updater>,
+}
+
+/// Statistics about the updates performed by an `Updater`
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct UpdaterStats {
+    /// Number of updates performed since the updater was started
+    pub ticks: u64,
+    /// Number of updates skipped because the thread woke up more than a
+    /// period after their deadline
+    pub missed_ticks: u64,
+    /// Maximum delay observed between a deadline and the actual update
+    pub max_lateness: Duration,
+}
+
+// The state shared with the updater thread
+#[derive(Debug, Default)]
+struct Shared {
+    running: AtomicBool,
+    // The update period, in nanoseconds
+    period: AtomicU64,
+    ticks: AtomicU64,
+    missed_ticks: AtomicU64,
+    max_lateness: AtomicU64,
+    // Incremented to wake the updater thread up before its next deadline
+    wakeups: AtomicU32,
+}
+
+// Schedules the updates at absolute deadlines, so that the time spent
+// updating and the scheduling delays do not accumulate. The wait for the
+// next deadline can be interrupted, so that the thread stops or applies a new
+// period immediately.
+struct Ticker {
+    shared: Arc<Shared>,
+    period: time::Duration,
+    next: time::Duration,
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    epoch: time::Instant,
+}
+
+struct Callback {
+    interval: Duration,
+    next: Option<Instant>,
+    // `None` once the callback has panicked
+    f: Option<Box<dyn FnMut(Instant) + Send>>,
+}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callback")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Callback {
+    fn run(&mut self, now: Instant) {
+        if matches!(self.next, Some(next) if now < next) {
+            return;
+        }
+        self.next = Some(now + self.interval);
+        let f = match &mut self.f {
+            Some(f) => f,
+            None => return,
+        };
+        // A panicking callback must not stop the updates, but it is not
+        // called again, so that the panic is only reported once
+        if panic::catch_unwind(AssertUnwindSafe(|| f(now))).is_err() {
+            self.f = None;
+        }
+    }
+}
+
+/// A builder to configure an `Updater` before starting it
+#[derive(Debug)]
+pub struct UpdaterBuilder {
+    updater: Updater,
+}
+
 impl Updater {
     /// Spawns a background task to call `Instant::update()` periodically
+    ///
+    /// Returns an error if the update period is zero.
     pub fn start(mut self) -> Result<Self, io::Error> {
-        let period = self.period;
-        let running = self.running.clone();
-        running.store(true, Ordering::Relaxed);
-        let th: thread::JoinHandle<()> = thread::Builder::new()
-            .name("coarsetime".to_string())
-            .spawn(move || {
-                while running.load(Ordering::Relaxed) {
-                    thread::sleep(period);
-                    Instant::update();
-                    Clock::update();
+        if self.shared.period.load(Ordering::Relaxed) == 0 {
+            return Err(zero_period_error());
+        }
+        let caches = self.caches;
+        let clock = self.clock;
+        let cpus = self.cpus.clone();
+        let priority = self.priority;
+        let mut callbacks = mem::take(&mut self.callbacks);
+        let shared = self.shared.clone();
+        shared.running.store(true, Ordering::Relaxed);
+        let (setup_tx, setup_rx) = mpsc::channel();
+        let mut builder = thread::Builder::new().name(self.name.clone());
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let th: thread::JoinHandle<()> = builder.spawn(move || {
+            let setup = configure_thread(cpus.as_deref(), priority);
+            let failed = setup.is_err();
+            let _ = setup_tx.send(setup);
+            if failed {
+                return;
+            }
+            #[cfg(feature = "tsc")]
+            if caches != Caches::Clock && clock.is_global() {
+                TscInstant::calibrate();
+            }
+            let mut ticker = Ticker::new(shared.clone());
+            while ticker.wait() {
+                update(caches, clock);
+                shared.ticks.fetch_add(1, Ordering::Relaxed);
+                if !callbacks.is_empty() {
+                    let now = match caches {
+                        Caches::Clock => Instant::now_without_cache_update(),
+                        _ => clock.recent(),
+                    };
+                    for callback in &mut callbacks {
+                        callback.run(now);
+                    }
                 }
-            })?;
+            }
+        })?;
+        if let Err(e) = setup_rx.recv().unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the updater thread failed to start",
+            ))
+        }) {
+            self.shared.running.store(false, Ordering::Relaxed);
+            let _ = th.join();
+            return Err(e);
+        }
         self.th = Some(th);
-        Instant::update();
-        Clock::update();
+        update(caches, clock);
         Ok(self)
     }
 
-    /// Stops the periodic updates
+    /// Spawns a background task to call `Instant::update()` periodically,
+    /// and returns a guard that stops it when dropped
+    pub fn start_guarded(self) -> Result<UpdaterGuard, io::Error> {
+        Ok(UpdaterGuard {
+            updater: Some(self.start()?),
+        })
+    }
+
+    /// Stops the periodic updates.
+    ///
+    /// Returns an error if the updater was not started.
     pub fn stop(mut self) -> Result<(), io::Error> {
-        self.running.store(false, Ordering::Relaxed);
-        self.th
+        let th = self
+            .th
             .take()
-            .expect("updater is not running")
-            .join()
-            .map_err(|_| {
-                io::Error::new(io::ErrorKind::Other, "failed to properly stop the updater")
-            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "updater is not running"))?;
+        self.shared.running.store(false, Ordering::Relaxed);
+        self.shared.wake(th.thread());
+        th.join().map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "failed to properly stop the updater")
+        })
+    }
+
+    /// Changes the update period.
+    ///
+    /// If the updater is running, the next update is rescheduled to happen
+    /// one new period after the previous one.
+    ///
+    /// Returns an error if the period is shorter than a nanosecond.
+    pub fn set_period(&self, period: Duration) -> Result<(), io::Error> {
+        let period = period_to_nanos(period.into());
+        if period == 0 {
+            return Err(zero_period_error());
+        }
+        self.shared.period.store(period, Ordering::Relaxed);
+        if let Some(th) = &self.th {
+            self.shared.wake(th.thread());
+        }
+        Ok(())
+    }
+
+    /// Returns the update period
+    pub fn period(&self) -> Duration {
+        time::Duration::from_nanos(self.shared.period.load(Ordering::Relaxed)).into()
     }
 
     /// Creates a new `Updater` with the specified update period, in
     /// milliseconds.
     pub fn new(period_millis: u64) -> Updater {
+        let shared = Shared::default();
+        shared.period.store(
+            period_to_nanos(time::Duration::from_millis(period_millis)),
+            Ordering::Relaxed,
+        );
         Updater {
-            period: time::Duration::from_millis(period_millis),
-            running: Arc::new(AtomicBool::new(false)),
+            name: DEFAULT_THREAD_NAME.to_string(),
+            stack_size: None,
+            cpus: None,
+            priority: None,
+            caches: Caches::default(),
+            clock: CoarseClock::global(),
+            callbacks: Vec::new(),
+            shared: Arc::new(shared),
             th: None,
         }
     }
+
+    /// Registers a function to be called by the updater thread after every
+    /// update, with the updated instant.
+    ///
+    /// Callbacks should return quickly, as they delay the next update.
+    /// If a callback panics, the panic is caught and the updates keep going,
+    /// unless the application is configured to abort on panic. A callback
+    /// that panicked is not called again.
+    pub fn on_tick<F>(self, f: F) -> Updater
+    where
+        F: FnMut(Instant) + Send + 'static,
+    {
+        self.on_tick_every(Duration::from_u64(0), f)
+    }
+
+    /// Registers a function to be called by the updater thread after an
+    /// update, at most once per `interval`.
+    ///
+    /// The interval is rounded up to a multiple of the update period.
+    pub fn on_tick_every<F>(mut self, interval: Duration, f: F) -> Updater
+    where
+        F: FnMut(Instant) + Send + 'static,
+    {
+        self.callbacks.push(Callback {
+            interval,
+            next: None,
+            f: Some(Box::new(f)),
+        });
+        self
+    }
+
+    /// Returns statistics about the updates performed so far.
+    ///
+    /// A large number of missed ticks, or a large maximum lateness, means
+    /// that the updater thread is not scheduled often enough for the
+    /// configured period.
+    pub fn stats(&self) -> UpdaterStats {
+        UpdaterStats {
+            ticks: self.shared.ticks.load(Ordering::Relaxed),
+            missed_ticks: self.shared.missed_ticks.load(Ordering::Relaxed),
+            max_lateness: Duration::from_u64(self.shared.max_lateness.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Returns a builder to configure a new `Updater`
+    pub fn builder() -> UpdaterBuilder {
+        UpdaterBuilder::new()
+    }
+}
+
+impl UpdaterBuilder {
+    /// Creates a builder for an `Updater` refreshing all the cached values
+    /// every 250 milliseconds, from a thread named "coarsetime".
+    pub fn new() -> UpdaterBuilder {
+        let updater = Updater::new(0);
+        updater
+            .shared
+            .period
+            .store(period_to_nanos(DEFAULT_PERIOD), Ordering::Relaxed);
+        UpdaterBuilder { updater }
+    }
+
+    /// Sets the update period, that can be shorter than a millisecond
+    pub fn period(self, period: Duration) -> UpdaterBuilder {
+        self.updater
+            .shared
+            .period
+            .store(period_to_nanos(period.into()), Ordering::Relaxed);
+        self
+    }
+
+    /// Sets the name of the updater thread
+    pub fn name<T: Into<String>>(mut self, name: T) -> UpdaterBuilder {
+        self.updater.name = name.into();
+        self
+    }
+
+    /// Sets the stack size of the updater thread, in bytes
+    pub fn stack_size(mut self, stack_size: usize) -> UpdaterBuilder {
+        self.updater.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Pins the updater thread to the specified set of CPUs.
+    ///
+    /// This is only supported on Linux and Android. On other platforms,
+    /// starting the updater returns an error.
+    pub fn cpu_affinity(mut self, cpus: &[usize]) -> UpdaterBuilder {
+        self.updater.cpus = Some(cpus.to_vec());
+        self
+    }
+
+    /// Sets the scheduling priority of the updater thread.
+    ///
+    /// Starting the updater returns an error if the priority is not supported
+    /// by the platform, or if the process lacks the required privileges.
+    pub fn priority(mut self, priority: ThreadPriority) -> UpdaterBuilder {
+        self.updater.priority = Some(priority);
+        self
+    }
+
+    /// Selects the cached values to refresh
+    pub fn caches(mut self, caches: Caches) -> UpdaterBuilder {
+        self.updater.caches = caches;
+        self
+    }
+
+    /// Selects the `CoarseClock` instance to refresh, instead of the
+    /// process-wide default one.
+    ///
+    /// `BootInstant` and `TscInstant` are only refreshed along with the
+    /// default instance.
+    pub fn clock(mut self, clock: &'static CoarseClock) -> UpdaterBuilder {
+        self.updater.clock = clock;
+        self
+    }
+
+    /// Returns the configured `Updater`, that still has to be started.
+    ///
+    /// Returns an error if the update period is zero, as the updater thread
+    /// would then never sleep.
+    pub fn build(self) -> Result<Updater, io::Error> {
+        if self.updater.shared.period.load(Ordering::Relaxed) == 0 {
+            return Err(zero_period_error());
+        }
+        Ok(self.updater)
+    }
+}
+
+impl Default for UpdaterBuilder {
+    fn default() -> UpdaterBuilder {
+        Self::new()
+    }
+}
+
+impl UpdaterGuard {
+    /// Stops the periodic updates, reporting errors that dropping the guard
+    /// would ignore
+    pub fn stop(mut self) -> Result<(), io::Error> {
+        match self.updater.take() {
+            Some(updater) => updater.stop(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for UpdaterGuard {
+    type Target = Updater;
+
+    fn deref(&self) -> &Updater {
+        // The updater is only taken by `stop()` and `drop()`
+        self.updater.as_ref().unwrap()
+    }
+}
+
+impl Drop for UpdaterGuard {
+    fn drop(&mut self) {
+        if let Some(updater) = self.updater.take() {
+            let _ = updater.stop();
+        }
+    }
+}
+
+impl Shared {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn wake(&self, _thread: &thread::Thread) {
+        self.wakeups.fetch_add(1, Ordering::Release);
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                &self.wakeups as *const AtomicU32,
+                libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                1,
+            )
+        };
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn wake(&self, thread: &thread::Thread) {
+        thread.unpark();
+    }
+}
+
+fn period_to_nanos(period: time::Duration) -> u64 {
+    u64::try_from(period.as_nanos()).unwrap_or(u64::MAX)
+}
+
+fn zero_period_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the update period must not be zero",
+    )
+}
+
+pub(crate) fn update(caches: Caches, clock: &CoarseClock) {
+    match caches {
+        Caches::Instant => clock.update_instant(),
+        Caches::Clock => clock.update_since_epoch(),
+        Caches::Both => clock.update(),
+    }
+    if caches != Caches::Clock && clock.is_global() {
+        BootInstant::update();
+        #[cfg(feature = "tsc")]
+        TscInstant::recalibrate();
+    }
+}
+
+impl Ticker {
+    fn new(shared: Arc<Shared>) -> Ticker {
+        let period = time::Duration::from_nanos(shared.period.load(Ordering::Relaxed));
+        let mut ticker = Ticker {
+            shared,
+            period,
+            next: time::Duration::ZERO,
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            epoch: time::Instant::now(),
+        };
+        ticker.next = ticker.now() + period;
+        ticker
+    }
+
+    /// Sleeps until the next deadline, and schedules the following one.
+    /// Returns `false` if the updater was stopped in the meantime.
+    ///
+    /// Deadlines that have already passed by more than a period are skipped
+    /// and reported as missed, instead of triggering a burst of updates.
+    fn wait(&mut self) -> bool {
+        loop {
+            // Read first, so that a wake-up sent after the checks below
+            // interrupts the sleep
+            let wakeups = self.shared.wakeups.load(Ordering::Acquire);
+            if !self.shared.running.load(Ordering::Relaxed) {
+                return false;
+            }
+            let period = time::Duration::from_nanos(self.shared.period.load(Ordering::Relaxed));
+            if period != self.period {
+                self.next = (self.next - self.period + period).max(self.now());
+                self.period = period;
+            }
+            let now = self.now();
+            if now >= self.next {
+                break;
+            }
+            self.sleep_until(self.next, wakeups);
+        }
+        let now = self.now();
+        let lateness = now.saturating_sub(self.next);
+        // The period is never zero, and the next deadline stays aligned
+        // with the previous ones
+        let period = self.period.as_nanos();
+        let phase = time::Duration::from_nanos((lateness.as_nanos() % period) as u64);
+        self.next = now + self.period - phase;
+        let missed = (lateness.as_nanos() / period) as u64;
+        if missed > 0 {
+            self.shared
+                .missed_ticks
+                .fetch_add(missed, Ordering::Relaxed);
+        }
+        self.shared
+            .max_lateness
+            .fetch_max(Duration::from(lateness).as_u64(), Ordering::Relaxed);
+        true
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn now(&self) -> time::Duration {
+        let mut tp = mem::MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_gettime(libc::CLOCK_MONOTONIC, tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        time::Duration::new(tp.tv_sec as u64, tp.tv_nsec as u32)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn now(&self) -> time::Duration {
+        self.epoch.elapsed()
+    }
+
+    // Sleeps until an absolute `CLOCK_MONOTONIC` deadline, or until the
+    // number of wake-ups differs from `wakeups`. The caller checks which one
+    // happened.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn sleep_until(&self, deadline: time::Duration, wakeups: u32) {
+        let tp = libc::timespec {
+            tv_sec: deadline.as_secs() as libc::time_t,
+            tv_nsec: deadline.subsec_nanos() as _,
+        };
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                &self.shared.wakeups as *const AtomicU32,
+                libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG,
+                wakeups,
+                &tp,
+                std::ptr::null::<u32>(),
+                libc::FUTEX_BITSET_MATCH_ANY,
+            )
+        };
+    }
+
+    // Without an interruptible absolute sleep, park until the deadline, that
+    // `Shared::wake()` can interrupt
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn sleep_until(&self, deadline: time::Duration, _wakeups: u32) {
+        thread::park_timeout(deadline.saturating_sub(self.now()));
+    }
+}
+
+fn configure_thread(cpus: Option<&[usize]>, priority: Option<ThreadPriority>) -> io::Result<()> {
+    if let Some(cpus) = cpus {
+        set_cpu_affinity(cpus)?;
+    }
+    match priority {
+        Some(ThreadPriority::RealTime(priority)) => set_realtime_priority(priority),
+        Some(ThreadPriority::Nice(nice)) => set_nice(nice),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_cpu_affinity(cpus: &[usize]) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid CPU index",
+            ));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_cpu_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "CPU affinity is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn set_realtime_priority(priority: u8) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority as libc::c_int,
+    };
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        e => Err(io::Error::from_raw_os_error(e)),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_realtime_priority(_priority: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "real-time priorities are not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_nice(nice: i8) -> io::Result<()> {
+    // On Linux, the priority of a thread can be set using its thread ID
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice as libc::c_int) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_nice(_nice: i8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "nice values are not supported on this platform",
+    ))
 }